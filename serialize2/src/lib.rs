@@ -0,0 +1,4221 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+#[cfg(feature = "cache")]
+use std::rc::Rc;
+use std::sync::LazyLock;
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+static STANDARD_ALPHABET: LazyLock<Alphabet> =
+    LazyLock::new(|| Alphabet::new(std::str::from_utf8(BASE64_CHARS).unwrap()).unwrap());
+/// Алфавит буквенно-цифрового режима QR-кодов (RFC 9285).
+const BASE45_CHARS: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Ступени ширины: `(граница, бит)` — выбирается первая ступень, для которой максимум меньше границы.
+///
+/// Набор ступеней не меняет смысл `bits_code` в заголовке: ширины 4, 7, 9 и 12 всегда пишутся кодами
+/// `00`, `01`, `10` и `11`, а любая другая ширина (например, 3 из `tiers-3-9`) — расширенным заголовком
+/// `MODE_WIDTH`, поэтому строки из сборок с разными ступенями декодируются одинаково.
+#[cfg(not(feature = "tiers-3-9"))]
+pub const TIERS: &[(u16, u8)] = &[(10, 4), (100, 7), (512, 9), (4096, 12)];
+#[cfg(feature = "tiers-3-9")]
+pub const TIERS: &[(u16, u8)] = &[(8, 3), (512, 9), (4096, 12)];
+
+pub fn determine_bits_per_number(numbers: &[u16]) -> u8 {
+    determine_bits_per_number_with(numbers, TIERS)
+}
+
+pub fn determine_bits_per_number_with(numbers: &[u16], tiers: &[(u16, u8)]) -> u8 {
+    let max = *numbers.iter().max().unwrap();
+    tiers
+        .iter()
+        .find(|&&(limit, _)| max < limit)
+        .map(|&(_, bits)| bits)
+        .expect("Ступени не покрывают максимальное число")
+}
+
+fn bits_code(bits_per_number: u8) -> Option<usize> {
+    match bits_per_number {
+        4 => Some(0b00),
+        7 => Some(0b01),
+        9 => Some(0b10),
+        12 => Some(0b11),
+        _ => None,
+    }
+}
+
+/// Способ упаковки чисел.
+///
+/// `Plain` — исходный формат. Остальные режимы записываются с расширенным заголовком:
+/// короткая длина `0` (флаг `0` и `000000`, пустой массив не сериализуется) и 4 бита кода режима.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Plain,
+    /// Frame of reference: минимум хранится один раз (9 бит), затем ширина (4 бита)
+    /// и разности `число - минимум`.
+    FrameOfReference,
+    /// Арифметическая прогрессия: первое число (9 бит), знак шага (1 бит) и его модуль
+    /// (9 бит); сами числа не хранятся. Подходит только спискам с постоянной разностью.
+    Arithmetic,
+    /// Исключения: основная часть узкой ширины (4 бита), затем список выбросов — число
+    /// выбросов как длина и пары «номер (10 бит), число (9 бит)»; на местах выбросов
+    /// в основной части нули. Выгоден, когда список широк из-за нескольких больших чисел.
+    Outliers,
+    /// Блоки FOR переменной длины по `optimal_block_boundaries`: перед каждым блоком его длина
+    /// минус один, минимум (9 бит) и ширина (4 бита). Пробуется только при `Effort::Max`.
+    VariableBlocks,
+    /// Позиции: самое частое число (9 бит) заполняет всё, а каждое другое различное число
+    /// хранится один раз со списком своих позиций разностями. Выгоден, когда различных чисел
+    /// мало, а встречаются они вразброс.
+    Positions,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 6] = [
+        Mode::Plain,
+        Mode::FrameOfReference,
+        Mode::Arithmetic,
+        Mode::Outliers,
+        Mode::VariableBlocks,
+        Mode::Positions,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Plain => "plain",
+            Mode::FrameOfReference => "frame_of_reference",
+            Mode::Arithmetic => "arithmetic",
+            Mode::Outliers => "outliers",
+            Mode::VariableBlocks => "variable_blocks",
+            Mode::Positions => "positions",
+        }
+    }
+
+    /// Можно ли записать `numbers` в этом режиме (`serialize_with_mode` иначе паникует).
+    pub fn applies_to(self, numbers: &[u16]) -> bool {
+        match self {
+            Mode::Plain | Mode::FrameOfReference | Mode::Outliers | Mode::VariableBlocks | Mode::Positions => true,
+            Mode::Arithmetic => arithmetic_step(numbers).is_some(),
+        }
+    }
+}
+
+/// Постоянная разность непустого списка; для одного числа — 0.
+fn arithmetic_step(numbers: &[u16]) -> Option<i16> {
+    let step = match numbers {
+        [] => return None,
+        [_] => 0,
+        [first, second, ..] => *second as i16 - *first as i16,
+    };
+    numbers.windows(2).all(|w| w[1] as i16 - w[0] as i16 == step).then_some(step)
+}
+
+const MODE_FRAME_OF_REFERENCE: usize = 0;
+/// Явная ширина: длина, затем ширина числа (4 бита) и числа этой ширины.
+const MODE_WIDTH: usize = 1;
+/// Числа с метками: длина, ширина числа (4 бита), ширина метки минус один (3 бита),
+/// затем пары «число, метка» подряд.
+const MODE_TAGGED: usize = 2;
+/// Блоки FOR: длина, размер блока минус один (6 бит), затем у каждого блока свой минимум
+/// (9 бит) и ширина (4 бита) перед его числами.
+const MODE_BLOCKED: usize = 3;
+/// Частоты: число пар, ширина значения (4 бита), ширина счётчика (6 бит), затем пары
+/// «значение, счётчик» по возрастанию значений.
+const MODE_COUNTS: usize = 4;
+const MODE_ARITHMETIC: usize = 5;
+/// Числа со знаком: длина, ширина (4 бита), затем числа в zigzag-кодировке.
+const MODE_ZIGZAG: usize = 6;
+const MODE_OUTLIERS: usize = 7;
+/// Разности неубывающего списка: длина, первое число (9 бит), ширина (4 бита), затем
+/// разности соседних чисел этой ширины.
+const MODE_DELTA: usize = 8;
+/// Блоки переменной длины: длина, затем у каждого блока длина минус один (`block_len_bits`
+/// от длины записи), минимум (9 бит) и ширина (4 бита) перед его числами.
+const MODE_VARIABLE_BLOCKS: usize = 9;
+/// Позиции: длина, число явных значений (как длина), заполняющее число (9 бит), затем у каждого
+/// явного значения по возрастанию: значение (9 бит), число позиций (`bit_width(длина)` бит),
+/// ширина разрыва (4 бита) и разрывы `позиция - предыдущая - 1` (первая — от -1).
+const MODE_POSITIONS: usize = 10;
+
+/// Размер блока для `serialize_blocked` по умолчанию: на медленно растущих и отсортированных
+/// списках из 500-600 чисел блоки по 8-12 короче, чем по 16 и больше (заголовок блока — 13 бит).
+pub const DEFAULT_BLOCK_SIZE: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SerializeError {
+    InvalidWidth(u8),
+    InvalidTagWidth(u8),
+    InvalidBlockSize(usize),
+    InvalidFrameSize(usize),
+    TagTooWide { index: usize, tag: u8, tag_bits: u8 },
+    ValueTooWide { index: usize, value: u16, width: u8 },
+    TooManyNumbers(usize),
+    OutputTooLong { len: usize, max: usize },
+    OutputTooLarge { len: usize, max: usize },
+    SizeOverflow { count: usize, width: u8 },
+    PaddingInAlphabet,
+    SignedOutOfRange { index: usize, value: i16 },
+    NotSorted { index: usize, prev: u16, value: u16 },
+    ModeNotApplicable(Mode),
+    Invalid(Vec<Violation>),
+}
+
+/// Одно нарушение ограничений `serialize`, найденное `validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    Empty,
+    TooManyNumbers(usize),
+    OutOfRange { index: usize, value: u16 },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::Empty => write!(f, "list is empty"),
+            Violation::TooManyNumbers(count) => write!(f, "{} numbers (max 1023)", count),
+            Violation::OutOfRange { index, value } => write!(f, "value {} at index {} is outside 1-300", value, index),
+        }
+    }
+}
+
+impl std::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializeError::InvalidWidth(width) => write!(f, "Unsupported width {} (expected 1-15)", width),
+            SerializeError::InvalidTagWidth(bits) => write!(f, "Unsupported tag width {} (expected 1-8)", bits),
+            SerializeError::InvalidBlockSize(size) => write!(f, "Unsupported block size {} (expected 1-64)", size),
+            SerializeError::InvalidFrameSize(size) => write!(f, "Unsupported frame size {} (expected 1-1023)", size),
+            SerializeError::TagTooWide { index, tag, tag_bits } => {
+                write!(f, "Tag {} at index {} does not fit in {} bits", tag, index, tag_bits)
+            }
+            SerializeError::ValueTooWide { index, value, width } => {
+                write!(f, "Value {} at index {} does not fit in {} bits", value, index, width)
+            }
+            SerializeError::TooManyNumbers(count) => write!(f, "Too many numbers: {} (max 1023)", count),
+            SerializeError::OutputTooLong { len, max } => {
+                write!(f, "Serialized length {} exceeds the fixed length {}", len, max)
+            }
+            SerializeError::OutputTooLarge { len, max } => {
+                write!(f, "Serialized length {} exceeds the limit of {}", len, max)
+            }
+            SerializeError::SizeOverflow { count, width } => {
+                write!(f, "Size of {} numbers of {} bits overflows usize", count, width)
+            }
+            SerializeError::SignedOutOfRange { index, value } => {
+                write!(f, "Value {} at index {} is outside -300..=300", value, index)
+            }
+            SerializeError::NotSorted { index, prev, value } => {
+                write!(f, "Value {} at index {} is less than the previous value {}", value, index, prev)
+            }
+            SerializeError::ModeNotApplicable(mode) => write!(f, "Mode {} does not apply to this list", mode.name()),
+            SerializeError::PaddingInAlphabet => {
+                write!(f, "Alphabet contains the fixed-length padding char {:?}", FIXED_PADDING)
+            }
+            SerializeError::Invalid(violations) => {
+                write!(f, "Invalid input: ")?;
+                for (i, violation) in violations.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", violation)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SerializeError {}
+
+/// Проверяет длину и диапазон до `serialize` и возвращает сразу все нарушения.
+pub fn validate(numbers: &[u16]) -> Result<(), SerializeError> {
+    let mut violations = Vec::new();
+    if numbers.is_empty() {
+        violations.push(Violation::Empty);
+    }
+    if numbers.len() > 1023 {
+        violations.push(Violation::TooManyNumbers(numbers.len()));
+    }
+    violations.extend(
+        numbers
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| !(1..=300).contains(*n))
+            .map(|(index, &value)| Violation::OutOfRange { index, value }),
+    );
+
+    if violations.is_empty() { Ok(()) } else { Err(SerializeError::Invalid(violations)) }
+}
+
+fn bit_width(value: u16) -> u8 {
+    (u16::BITS - value.leading_zeros()) as u8
+}
+
+fn push_bits(bitstr: &mut String, value: usize, width: usize) {
+    if width > 0 {
+        *bitstr += &format!("{:0width$b}", value, width = width);
+    }
+}
+
+fn push_count(bitstr: &mut String, count: usize) {
+    if count < 64 {
+        bitstr.push('0');
+        push_bits(bitstr, count, 6);
+    } else {
+        bitstr.push('1');
+        push_bits(bitstr, count, 10);
+    }
+}
+
+/// Ширина одного значения в выбранном режиме.
+pub fn bits_per_value(numbers: &[u16], mode: Mode) -> u8 {
+    match mode {
+        Mode::Plain => determine_bits_per_number(numbers),
+        Mode::FrameOfReference => {
+            bit_width(numbers.iter().max().unwrap() - numbers.iter().min().unwrap())
+        }
+        Mode::Arithmetic => 0,
+        Mode::Outliers => outlier_width(numbers),
+        Mode::VariableBlocks => block_ranges(numbers)
+            .map(|block| bit_width(block.iter().max().unwrap() - block.iter().min().unwrap()))
+            .max()
+            .unwrap_or(0),
+        Mode::Positions => {
+            let groups = position_groups(numbers);
+            groups[..groups.len() - 1].iter().map(|(_, positions)| gap_width(positions)).max().unwrap_or(0)
+        }
+    }
+}
+
+/// Различные числа с их позициями по возрастанию; самое частое (при равенстве — меньшее) стоит
+/// последним и в `MODE_POSITIONS` не получает списка позиций.
+fn position_groups(numbers: &[u16]) -> Vec<(u16, Vec<usize>)> {
+    let mut groups: BTreeMap<u16, Vec<usize>> = BTreeMap::new();
+    for (index, &num) in numbers.iter().enumerate() {
+        groups.entry(num).or_default().push(index);
+    }
+    let mut groups: Vec<(u16, Vec<usize>)> = groups.into_iter().collect();
+    if let Some(fill) = (0..groups.len()).rev().max_by_key(|&i| groups[i].1.len()) {
+        let fill = groups.remove(fill);
+        groups.push(fill);
+    }
+    groups
+}
+
+/// Ширина разрывов между позициями одного значения.
+fn gap_width(positions: &[usize]) -> u8 {
+    let gaps = std::iter::once(positions[0]).chain(positions.windows(2).map(|w| w[1] - w[0] - 1));
+    gaps.map(|gap| bit_width(gap as u16)).max().unwrap()
+}
+
+/// Ширина поля длины блока в `MODE_VARIABLE_BLOCKS`: хватает на `count - 1`.
+fn block_len_bits(count: usize) -> usize {
+    bit_width(count.saturating_sub(1) as u16) as usize
+}
+
+/// Границы блоков FOR переменной длины с наименьшим суммарным числом бит: возвращает концы
+/// блоков (не включая), последний равен `numbers.len()`. Блок стоит заголовок — длина
+/// (`block_len_bits`), минимум и ширина — плюс `длина * ширина` разностей с минимумом.
+/// Динамическое программирование за O(n²): для каждого конца перебираются все начала.
+pub fn optimal_block_boundaries(numbers: &[u16]) -> Vec<usize> {
+    let header_bits = block_len_bits(numbers.len()) + 9 + 4;
+    // best[j] — наименьшая стоимость первых j чисел и начало последнего блока.
+    let mut best = vec![(0usize, 0usize); numbers.len() + 1];
+    for end in 1..=numbers.len() {
+        let (mut min, mut max) = (u16::MAX, 0);
+        best[end] = (usize::MAX, 0);
+        for start in (0..end).rev() {
+            min = min.min(numbers[start]);
+            max = max.max(numbers[start]);
+            let cost = best[start].0 + header_bits + (end - start) * bit_width(max - min) as usize;
+            if cost < best[end].0 {
+                best[end] = (cost, start);
+            }
+        }
+    }
+
+    let mut boundaries = Vec::new();
+    let mut end = numbers.len();
+    while end > 0 {
+        boundaries.push(end);
+        end = best[end].1;
+    }
+    boundaries.reverse();
+    boundaries
+}
+
+fn block_ranges(numbers: &[u16]) -> impl Iterator<Item = &[u16]> {
+    let boundaries = optimal_block_boundaries(numbers);
+    let starts = std::iter::once(0).chain(boundaries.clone());
+    starts.zip(boundaries).map(move |(start, end)| &numbers[start..end])
+}
+
+/// Ширина основной части `Mode::Outliers` с самой короткой записью: каждое число шире неё
+/// стоит 19 бит в списке выбросов плюс нули на его месте.
+fn outlier_width(numbers: &[u16]) -> u8 {
+    (0..=9)
+        .min_by_key(|&width| {
+            let outliers = numbers.iter().filter(|&&num| bit_width(num) > width).count();
+            outliers * 19 + numbers.len() * width as usize
+        })
+        .unwrap()
+}
+
+fn push_width_header(bitstr: &mut String, count: usize, width: u8) {
+    push_count(bitstr, 0);
+    push_bits(bitstr, MODE_WIDTH, 4);
+    push_count(bitstr, count);
+    push_bits(bitstr, width as usize, 4);
+}
+
+/// Обычный заголовок с `bits_code`, если у ширины есть код; иначе (и для пустого списка,
+/// чья нулевая длина зарезервирована под расширенный заголовок) — `MODE_WIDTH`.
+fn push_plain_header(bitstr: &mut String, count: usize, width: u8) {
+    match bits_code(width) {
+        Some(code) if count > 0 => {
+            push_count(bitstr, count);
+            push_bits(bitstr, code, 2);
+        }
+        _ => push_width_header(bitstr, count, width),
+    }
+}
+
+fn assert_in_range(numbers: &[u16]) {
+    if numbers.iter().any(|n| !(1..=300).contains(n)) {
+        panic!("Все числа должны быть в диапазоне 1-300");
+    }
+}
+
+fn to_bitstring(numbers: &[u16]) -> String {
+    to_bitstring_with_mode(numbers, Mode::Plain)
+}
+
+/// Счётчик в заголовке вмещает не больше 1023 чисел, а пустой список не из чего кодировать.
+fn assert_len(numbers: &[u16]) {
+    if !(1..=1023).contains(&numbers.len()) {
+        panic!("Нужно от 1 до 1023 чисел");
+    }
+}
+
+fn to_bitstring_with_mode(numbers: &[u16], mode: Mode) -> String {
+    assert_len(numbers);
+    assert_in_range(numbers);
+    to_bitstring_unchecked(numbers, mode)
+}
+
+/// `to_bitstring_with_mode` без проверки диапазона.
+fn to_bitstring_unchecked(numbers: &[u16], mode: Mode) -> String {
+    let mut bitstr = String::new();
+
+    match mode {
+        Mode::Plain => {
+            let bits_per_number = determine_bits_per_number(numbers);
+            push_plain_header(&mut bitstr, numbers.len(), bits_per_number);
+
+            for &num in numbers {
+                push_bits(&mut bitstr, num as usize, bits_per_number as usize);
+            }
+        }
+        Mode::FrameOfReference => push_frame_of_reference(&mut bitstr, numbers),
+        Mode::Arithmetic => {
+            let step = arithmetic_step(numbers).expect("Числа не образуют арифметическую прогрессию");
+            push_count(&mut bitstr, 0);
+            push_bits(&mut bitstr, MODE_ARITHMETIC, 4);
+            push_count(&mut bitstr, numbers.len());
+            push_bits(&mut bitstr, numbers[0] as usize, 9);
+            push_bits(&mut bitstr, (step < 0) as usize, 1);
+            push_bits(&mut bitstr, step.unsigned_abs() as usize, 9);
+        }
+        Mode::Outliers => push_outliers(&mut bitstr, numbers),
+        Mode::Positions => {
+            let groups = position_groups(numbers);
+            let (explicit, fill) = groups.split_at(groups.len() - 1);
+            push_count(&mut bitstr, 0);
+            push_bits(&mut bitstr, MODE_POSITIONS, 4);
+            push_count(&mut bitstr, numbers.len());
+            push_count(&mut bitstr, explicit.len());
+            push_bits(&mut bitstr, fill[0].0 as usize, 9);
+            for (value, positions) in explicit {
+                let width = gap_width(positions) as usize;
+                push_bits(&mut bitstr, *value as usize, 9);
+                push_bits(&mut bitstr, positions.len(), bit_width(numbers.len() as u16) as usize);
+                push_bits(&mut bitstr, width, 4);
+                let mut next = 0;
+                for &position in positions {
+                    push_bits(&mut bitstr, position - next, width);
+                    next = position + 1;
+                }
+            }
+        }
+        Mode::VariableBlocks => {
+            push_count(&mut bitstr, 0);
+            push_bits(&mut bitstr, MODE_VARIABLE_BLOCKS, 4);
+            push_count(&mut bitstr, numbers.len());
+            for block in block_ranges(numbers) {
+                let min = *block.iter().min().unwrap();
+                let width = bit_width(block.iter().max().unwrap() - min) as usize;
+                push_bits(&mut bitstr, block.len() - 1, block_len_bits(numbers.len()));
+                push_bits(&mut bitstr, min as usize, 9);
+                push_bits(&mut bitstr, width, 4);
+                for &num in block {
+                    push_bits(&mut bitstr, (num - min) as usize, width);
+                }
+            }
+        }
+    }
+
+    bitstr
+}
+
+fn push_outliers(bitstr: &mut String, numbers: &[u16]) {
+    let width = outlier_width(numbers);
+    let is_outlier = |num: u16| bit_width(num) > width;
+
+    push_count(bitstr, 0);
+    push_bits(bitstr, MODE_OUTLIERS, 4);
+    push_count(bitstr, numbers.len());
+    push_bits(bitstr, width as usize, 4);
+    push_count(bitstr, numbers.iter().filter(|&&num| is_outlier(num)).count());
+    for (index, &num) in numbers.iter().enumerate().filter(|&(_, &num)| is_outlier(num)) {
+        push_bits(bitstr, index, 10);
+        push_bits(bitstr, num as usize, 9);
+    }
+
+    for &num in numbers {
+        push_bits(bitstr, if is_outlier(num) { 0 } else { num as usize }, width as usize);
+    }
+}
+
+fn push_frame_of_reference(bitstr: &mut String, numbers: &[u16]) {
+    let min = *numbers.iter().min().unwrap();
+    let width = bits_per_value(numbers, Mode::FrameOfReference) as usize;
+
+    push_count(bitstr, 0);
+    push_bits(bitstr, MODE_FRAME_OF_REFERENCE, 4);
+    push_count(bitstr, numbers.len());
+    push_bits(bitstr, min as usize, 9);
+    push_bits(bitstr, width, 4);
+
+    for &num in numbers {
+        push_bits(bitstr, (num - min) as usize, width);
+    }
+}
+
+/// Дополняет биты нулями до кратного 6 и кодирует в base64.
+///
+/// Число битов дополнения не хранится: декодирование всегда управляется заголовком
+/// (длина и ширина), поэтому конец записи — это последний бит последнего числа,
+/// а запись занимает `ceil(бит / 6)` символов. Остаток последнего символа всегда
+/// меньше 6 бит, так что склеенные записи однозначно разделяются по границе символа.
+fn bitstring_to_base64(bitstr: String) -> String {
+    bitstring_to_text(bitstr, &STANDARD_ALPHABET)
+}
+
+fn bitstring_to_text(bitstr: String, alphabet: &Alphabet) -> String {
+    let mut result = vec![0; bitstr.len().div_ceil(6)];
+    write_text(&bitstr, alphabet, &mut result);
+    String::from_utf8(result).unwrap()
+}
+
+/// Записывает символы алфавита для `bitstr` (дополненной нулями до кратного 6) в начало `out`.
+fn write_text(bitstr: &str, alphabet: &Alphabet, out: &mut [u8]) -> usize {
+    let mut written = 0;
+    for chunk in bitstr.as_bytes().chunks(6) {
+        let chunk_str = std::str::from_utf8(chunk).unwrap();
+        let val = u8::from_str_radix(chunk_str, 2).unwrap() << (6 - chunk.len());
+        out[written] = alphabet.chars[val as usize];
+        debug_assert_ne!(alphabet.lookup[out[written] as usize], NOT_IN_ALPHABET, "символ вне алфавита");
+        written += 1;
+    }
+    written
+}
+
+/// Алфавит из 64 ASCII-символов вместе с таблицей обратного поиска.
+#[derive(Debug, Clone)]
+pub struct Alphabet {
+    chars: [u8; 64],
+    lookup: [u8; 256],
+}
+
+const NOT_IN_ALPHABET: u8 = u8::MAX;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlphabetError {
+    WrongLength(usize),
+    NonAscii(char),
+    Duplicate { ch: char, first: usize, second: usize },
+}
+
+impl std::fmt::Display for AlphabetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlphabetError::WrongLength(len) => write!(f, "Alphabet must have 64 chars, got {}", len),
+            AlphabetError::NonAscii(ch) => write!(f, "Alphabet char {:?} is not ASCII", ch),
+            AlphabetError::Duplicate { ch, first, second } => {
+                write!(f, "Alphabet char {:?} repeats at positions {} and {}", ch, first, second)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AlphabetError {}
+
+impl Alphabet {
+    /// Проверяет, что символы уникальны: иначе обратный поиск молча вернул бы первое совпадение.
+    pub fn new(chars: &str) -> Result<Self, AlphabetError> {
+        if let Some(ch) = chars.chars().find(|ch| !ch.is_ascii()) {
+            return Err(AlphabetError::NonAscii(ch));
+        }
+        let chars: [u8; 64] = chars
+            .as_bytes()
+            .try_into()
+            .map_err(|_| AlphabetError::WrongLength(chars.len()))?;
+
+        let mut lookup = [NOT_IN_ALPHABET; 256];
+        for (i, &c) in chars.iter().enumerate() {
+            if lookup[c as usize] != NOT_IN_ALPHABET {
+                let first = lookup[c as usize] as usize;
+                return Err(AlphabetError::Duplicate { ch: c as char, first, second: i });
+            }
+            lookup[c as usize] = i as u8;
+        }
+
+        Ok(Alphabet { chars, lookup })
+    }
+}
+
+pub fn serialize_with_alphabet(numbers: &[u16], alphabet: &Alphabet) -> String {
+    bitstring_to_text(to_bitstring(numbers), alphabet)
+}
+
+/// Паникует, если список пуст, длиннее 1023 чисел или содержит число вне 1-300;
+/// то же относится к `serialize_with_mode` и `serialize_best*`. Длинные списки кодирует
+/// `serialize_framed`, а `validate` проверяет вход без паники.
+pub fn serialize(numbers: &[u16]) -> String {
+    serialize_with_mode(numbers, Mode::Plain)
+}
+
+/// `serialize` без проверки диапазона — для горячих путей, где вход уже проверен (например,
+/// `validate`). Вызывающий гарантирует 1-1023 числа в диапазоне 1-300; иначе запись не
+/// определена и может не декодироваться, а пустой список или число от 4096 вызывают панику.
+pub fn serialize_unchecked(numbers: &[u16]) -> String {
+    bitstring_to_base64(to_bitstring_unchecked(numbers, Mode::Plain))
+}
+
+pub fn serialize_with_mode(numbers: &[u16], mode: Mode) -> String {
+    bitstring_to_base64(to_bitstring_with_mode(numbers, mode))
+}
+
+/// Пробует все подходящие режимы и возвращает самую короткую строку (при равенстве — `Plain`).
+/// Паникует на том же входе, что и `serialize`.
+pub fn serialize_best(numbers: &[u16]) -> String {
+    serialize_best_with_effort(numbers, Effort::Max)
+}
+
+/// Сколько режимов пробует `serialize_best_with_effort`: меньше кандидатов — быстрее,
+/// но запись может оказаться длиннее, чем у `Max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effort {
+    /// Один режим, выбранный по ширине чисел без пробного кодирования.
+    Fast,
+    /// Режим `Fast` и `Plain`.
+    Balanced,
+    /// Все подходящие режимы из `Mode::ALL`.
+    Max,
+}
+
+impl Effort {
+    fn candidates(self, numbers: &[u16]) -> Vec<Mode> {
+        match self {
+            Effort::Fast => vec![estimated_mode(numbers)],
+            Effort::Balanced => {
+                let mut modes = vec![Mode::Plain, estimated_mode(numbers)];
+                modes.dedup();
+                modes
+            }
+            // Только здесь пробуется `VariableBlocks`, самый дорогой по времени.
+            Effort::Max => Mode::ALL.iter().copied().filter(|mode| mode.applies_to(numbers)).collect(),
+        }
+    }
+}
+
+/// Режим по оценке длины: прогрессия короче всего, иначе сравниваются биты чисел
+/// с поправкой на 22 бита заголовка FOR (минимум, ширина и код режима).
+fn estimated_mode(numbers: &[u16]) -> Mode {
+    if Mode::Arithmetic.applies_to(numbers) {
+        return Mode::Arithmetic;
+    }
+    let bits = |mode| numbers.len() * bits_per_value(numbers, mode) as usize;
+    if bits(Mode::FrameOfReference) + 22 < bits(Mode::Plain) { Mode::FrameOfReference } else { Mode::Plain }
+}
+
+/// Самая короткая запись среди режимов, которые пробует `effort` (при равенстве — первый).
+pub fn serialize_best_with_effort(numbers: &[u16], effort: Effort) -> String {
+    best_with_effort(numbers, effort).0
+}
+
+/// `serialize_best` вместе с выбранным режимом.
+pub fn serialize_best_with_mode(numbers: &[u16]) -> (String, Mode) {
+    best_with_effort(numbers, Effort::Max)
+}
+
+fn best_with_effort(numbers: &[u16], effort: Effort) -> (String, Mode) {
+    effort
+        .candidates(numbers)
+        .into_iter()
+        .map(|mode| (serialize_with_mode(numbers, mode), mode))
+        .min_by_key(|(s, _)| s.len())
+        .unwrap()
+}
+
+/// Накопительная статистика кодирования для метрик: сколько раз выбран каждый режим и
+/// сколько байт прошло через кодер (на входе — по 2 байта на число, на выходе — символы строки).
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub calls: usize,
+    pub bytes_in: usize,
+    pub bytes_out: usize,
+    mode_counts: [usize; Mode::ALL.len()],
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode_count(&self, mode: Mode) -> usize {
+        self.mode_counts[mode as usize]
+    }
+
+    /// Доля вызовов, выбравших `mode` (0.0 до первого вызова).
+    pub fn mode_share(&self, mode: Mode) -> f64 {
+        if self.calls == 0 {
+            return 0.0;
+        }
+        self.mode_count(mode) as f64 / self.calls as f64
+    }
+
+    pub fn report(&self) -> String {
+        let modes: Vec<String> = Mode::ALL
+            .iter()
+            .map(|&mode| format!("{} {:.1}%", mode.name(), self.mode_share(mode) * 100.0))
+            .collect();
+        format!(
+            "Вызовов: {}, байт на входе: {}, на выходе: {}, режимы: {}",
+            self.calls,
+            self.bytes_in,
+            self.bytes_out,
+            modes.join(", ")
+        )
+    }
+}
+
+/// `serialize_best`, который учитывает выбранный режим и размеры в `stats`.
+pub fn serialize_with_stats(numbers: &[u16], stats: &mut Stats) -> String {
+    let (serialized, mode) = serialize_best_with_mode(numbers);
+    stats.calls += 1;
+    stats.bytes_in += std::mem::size_of_val(numbers);
+    stats.bytes_out += serialized.len();
+    stats.mode_counts[mode as usize] += 1;
+    serialized
+}
+
+/// Упаковывает числа с заданной шириной вместо выбранной по максимуму.
+/// Проверяется только, что каждое число помещается в `width` бит, так что через ширину 12
+/// можно записать числа до 4095 (диапазон `serialize` остаётся 1-300).
+pub fn serialize_with_width(numbers: &[u16], width: u8) -> Result<String, SerializeError> {
+    width_bitstring(numbers, width).map(bitstring_to_base64)
+}
+
+fn width_bitstring(numbers: &[u16], width: u8) -> Result<String, SerializeError> {
+    if !(1..=15).contains(&width) {
+        return Err(SerializeError::InvalidWidth(width));
+    }
+    if numbers.len() > 1023 {
+        return Err(SerializeError::TooManyNumbers(numbers.len()));
+    }
+    if let Some((index, &value)) = numbers.iter().enumerate().find(|&(_, &n)| bit_width(n) > width) {
+        return Err(SerializeError::ValueTooWide { index, value, width });
+    }
+
+    let mut bitstr = String::new();
+    push_plain_header(&mut bitstr, numbers.len(), width);
+    for &num in numbers {
+        push_bits(&mut bitstr, num as usize, width as usize);
+    }
+
+    Ok(bitstr)
+}
+
+/// Упаковывает пары «число, метка» в один поток; ширина числа выбирается как в `serialize`.
+pub fn serialize_tagged(values: &[(u16, u8)], tag_bits: u8) -> Result<String, SerializeError> {
+    if !(1..=8).contains(&tag_bits) {
+        return Err(SerializeError::InvalidTagWidth(tag_bits));
+    }
+    let numbers: Vec<u16> = values.iter().map(|&(num, _)| num).collect();
+    validate(&numbers)?;
+    if let Some((index, &(_, tag))) = values.iter().enumerate().find(|&(_, &(_, tag))| bit_width(tag as u16) > tag_bits) {
+        return Err(SerializeError::TagTooWide { index, tag, tag_bits });
+    }
+
+    let width = determine_bits_per_number(&numbers);
+    let mut bitstr = String::new();
+    push_count(&mut bitstr, 0);
+    push_bits(&mut bitstr, MODE_TAGGED, 4);
+    push_count(&mut bitstr, values.len());
+    push_bits(&mut bitstr, width as usize, 4);
+    push_bits(&mut bitstr, tag_bits as usize - 1, 3);
+    for &(num, tag) in values {
+        push_bits(&mut bitstr, num as usize, width as usize);
+        push_bits(&mut bitstr, tag as usize, tag_bits as usize);
+    }
+
+    Ok(bitstring_to_base64(bitstr))
+}
+
+/// Кодирует словарь «значение → сколько раз» парами, не разворачивая его в список.
+/// Значения, как и в `serialize`, должны быть в диапазоне 1-300.
+pub fn serialize_counts(map: &BTreeMap<u16, u32>) -> String {
+    let values: Vec<u16> = map.keys().copied().collect();
+    assert_in_range(&values);
+    let value_width = values.last().map_or(0, |&max| bit_width(max)) as usize;
+    let count_width = map.values().max().map_or(0, |&max| (u32::BITS - max.leading_zeros()) as usize);
+
+    let mut bitstr = String::new();
+    push_count(&mut bitstr, 0);
+    push_bits(&mut bitstr, MODE_COUNTS, 4);
+    push_count(&mut bitstr, map.len());
+    push_bits(&mut bitstr, value_width, 4);
+    push_bits(&mut bitstr, count_width, 6);
+    for (&value, &count) in map {
+        push_bits(&mut bitstr, value as usize, value_width);
+        push_bits(&mut bitstr, count as usize, count_width);
+    }
+
+    bitstring_to_base64(bitstr)
+}
+
+/// Числа со знаком в диапазоне -300..=300. Схема — zigzag (`0, -1, 1, -2, ...` → `0, 1, 2, 3, ...`):
+/// знак попадает в младший бит, поэтому ширина выбирается по модулю, как в `MODE_WIDTH`,
+/// и не превышает 10 бит. Ноль допустим.
+pub fn serialize_i16(numbers: &[i16]) -> Result<String, SerializeError> {
+    if numbers.len() > 1023 {
+        return Err(SerializeError::TooManyNumbers(numbers.len()));
+    }
+    if let Some((index, &value)) = numbers.iter().enumerate().find(|&(_, n)| !(-300..=300).contains(n)) {
+        return Err(SerializeError::SignedOutOfRange { index, value });
+    }
+
+    let zigzag: Vec<u16> = numbers.iter().map(|&n| ((n << 1) ^ (n >> 15)) as u16).collect();
+    let width = zigzag.iter().max().map_or(0, |&max| bit_width(max)) as usize;
+    let mut bitstr = String::new();
+    push_count(&mut bitstr, 0);
+    push_bits(&mut bitstr, MODE_ZIGZAG, 4);
+    push_count(&mut bitstr, numbers.len());
+    push_bits(&mut bitstr, width, 4);
+    for &num in &zigzag {
+        push_bits(&mut bitstr, num as usize, width);
+    }
+
+    Ok(bitstring_to_base64(bitstr))
+}
+
+/// Кодирует неубывающую последовательность разностями соседних чисел (`MODE_DELTA`), читается
+/// обычным `deserialize`. Порядок проверяется по ходу: первое убывание — `NotSorted`. Длина и
+/// ширина стоят в заголовке, поэтому до конца итератора копятся только разности (до 1023).
+/// Пустой список допустим.
+pub fn serialize_sorted_deltas<I: Iterator<Item = u16>>(iter: I) -> Result<String, SerializeError> {
+    let mut first = None;
+    let mut prev = 0;
+    let mut deltas = Vec::new();
+    for (index, value) in iter.enumerate() {
+        if !(1..=300).contains(&value) {
+            return Err(SerializeError::Invalid(vec![Violation::OutOfRange { index, value }]));
+        }
+        if index >= 1023 {
+            return Err(SerializeError::TooManyNumbers(index + 1));
+        }
+        match first {
+            None => first = Some(value),
+            Some(_) if value < prev => return Err(SerializeError::NotSorted { index, prev, value }),
+            Some(_) => deltas.push(value - prev),
+        }
+        prev = value;
+    }
+
+    let count = deltas.len() + first.is_some() as usize;
+    let width = deltas.iter().max().map_or(0, |&max| bit_width(max)) as usize;
+    let mut bitstr = String::new();
+    push_count(&mut bitstr, 0);
+    push_bits(&mut bitstr, MODE_DELTA, 4);
+    push_count(&mut bitstr, count);
+    push_bits(&mut bitstr, first.unwrap_or(0) as usize, 9);
+    push_bits(&mut bitstr, width, 4);
+    for &delta in &deltas {
+        push_bits(&mut bitstr, delta as usize, width);
+    }
+
+    Ok(bitstring_to_base64(bitstr))
+}
+
+pub const DATA_URI_PREFIX: &str = "data:application/octet-stream;base64,";
+
+/// Запись `serialize` в виде data: URI для HTML и JSON. Запись дополняется `=` до кратного
+/// четырём, как требует base64 (RFC 4648), поэтому URI читается и обычным base64-декодером.
+pub fn serialize_data_uri(numbers: &[u16]) -> String {
+    let mut serialized = serialize(numbers);
+    // Группа base64 из одного символа недопустима даже с `=`: добавляется символ нулевых бит,
+    // который декодер записи не читает.
+    if serialized.len() % 4 == 1 {
+        serialized.push('A');
+    }
+    let padding = (4 - serialized.len() % 4) % 4;
+    format!("{}{}{}", DATA_URI_PREFIX, serialized, "=".repeat(padding))
+}
+
+/// Обратное к `serialize_data_uri`: другой префикс (тип MIME или не base64) — `InvalidDataUri`.
+pub fn deserialize_data_uri(uri: &str) -> Result<Vec<u16>, DeserializeError> {
+    let payload = uri.strip_prefix(DATA_URI_PREFIX).ok_or(DeserializeError::InvalidDataUri)?;
+    deserialize(payload.trim_end_matches('='))
+}
+
+/// Символ дополнения `serialize_fixed`; в base64-алфавит не входит.
+pub const FIXED_PADDING: char = '=';
+
+/// Строка ровно из `len` символов: запись `serialize`, дополненная `FIXED_PADDING`.
+pub fn serialize_fixed(numbers: &[u16], len: usize) -> Result<String, SerializeError> {
+    pad_fixed(serialize(numbers), len)
+}
+
+fn pad_fixed(mut serialized: String, len: usize) -> Result<String, SerializeError> {
+    if serialized.len() > len {
+        return Err(SerializeError::OutputTooLong { len: serialized.len(), max: len });
+    }
+    serialized.extend(std::iter::repeat_n(FIXED_PADDING, len - serialized.len()));
+    Ok(serialized)
+}
+
+/// Делит числа на блоки по `block_size` и кодирует каждый блок от его минимума со своей шириной.
+pub fn serialize_blocked(numbers: &[u16], block_size: usize) -> Result<String, SerializeError> {
+    blocked_bitstring(numbers, block_size).map(bitstring_to_base64)
+}
+
+fn blocked_bitstring(numbers: &[u16], block_size: usize) -> Result<String, SerializeError> {
+    if !(1..=64).contains(&block_size) {
+        return Err(SerializeError::InvalidBlockSize(block_size));
+    }
+    validate(numbers)?;
+
+    let mut bitstr = String::new();
+    push_count(&mut bitstr, 0);
+    push_bits(&mut bitstr, MODE_BLOCKED, 4);
+    push_count(&mut bitstr, numbers.len());
+    push_bits(&mut bitstr, block_size - 1, 6);
+    for block in numbers.chunks(block_size) {
+        let min = *block.iter().min().unwrap();
+        let width = bit_width(block.iter().max().unwrap() - min) as usize;
+        push_bits(&mut bitstr, min as usize, 9);
+        push_bits(&mut bitstr, width, 4);
+        for &num in block {
+            push_bits(&mut bitstr, (num - min) as usize, width);
+        }
+    }
+
+    Ok(bitstr)
+}
+
+/// Кодирует множество по возрастанию лучшим режимом; пустое множество — явной шириной с нулевой длиной.
+/// Значения вне 1-300 из записей с явной шириной или FOR записываются как в `canonicalize`;
+/// больше 1023 значений в одну запись не помещается.
+fn serialize_set(values: &BTreeSet<u16>) -> Result<String, DeserializeError> {
+    let numbers: Vec<u16> = values.iter().copied().collect();
+    if numbers.len() > 1023 {
+        return Err(DeserializeError::TooManyElements { count: numbers.len(), max: 1023 });
+    }
+    if numbers.is_empty() {
+        return Ok(serialize_with_width(&numbers, 1).unwrap());
+    }
+    Ok(if numbers.iter().all(|n| (1..=300).contains(n)) {
+        serialize_best(&numbers)
+    } else {
+        serialize_out_of_range(&numbers)
+    })
+}
+
+/// Объединение нескольких сериализованных множеств (повторы внутри входов отбрасываются).
+pub fn union_serialized(inputs: &[&str]) -> Result<String, DeserializeError> {
+    let mut union = BTreeSet::new();
+    for input in inputs {
+        union.extend(deserialize(input)?);
+    }
+    serialize_set(&union)
+}
+
+/// Пересечение нескольких сериализованных множеств; для пустого списка входов — пустое множество.
+pub fn intersect_serialized(inputs: &[&str]) -> Result<String, DeserializeError> {
+    let mut sets = inputs
+        .iter()
+        .map(|input| deserialize(input).map(BTreeSet::from_iter))
+        .collect::<Result<Vec<BTreeSet<u16>>, _>>()?
+        .into_iter();
+    let first = sets.next().unwrap_or_default();
+    let intersection = sets.fold(first, |acc, set| &acc & &set);
+    serialize_set(&intersection)
+}
+
+fn count_bits(count: usize) -> usize {
+    if count < 64 { 7 } else { 11 }
+}
+
+/// Точная длина строки `serialize(numbers)`, вычисленная по заголовку без кодирования.
+/// Вход, который `serialize` не примет, отклоняется ошибкой `validate`; `SizeOverflow`, если
+/// число бит не помещается в `usize` (например, на 16-битных целях).
+pub fn estimated_len(numbers: &[u16]) -> Result<usize, SerializeError> {
+    validate(numbers)?;
+    plain_len(numbers.len(), determine_bits_per_number(numbers))
+}
+
+/// Длина записи с заголовком `push_plain_header` и `count` числами ширины `width`.
+fn plain_len(count: usize, width: u8) -> Result<usize, SerializeError> {
+    let header = plain_header_bits(count, width);
+    count
+        .checked_mul(width as usize)
+        .and_then(|bits| bits.checked_add(header))
+        .map(|bits| bits.div_ceil(6))
+        .ok_or(SerializeError::SizeOverflow { count, width })
+}
+
+fn plain_header_bits(count: usize, width: u8) -> usize {
+    match bits_code(width) {
+        Some(_) if count > 0 => count_bits(count) + 2,
+        _ => count_bits(0) + 4 + count_bits(count) + 4,
+    }
+}
+
+/// Биты заголовка записи `mode`: длины, код режима и поля, общие для всех чисел. Таблицы
+/// выбросов и позиций и заголовки блоков `VariableBlocks` хранят сами числа и сюда не входят.
+fn header_bits(numbers: &[u16], mode: Mode) -> usize {
+    let extended = count_bits(0) + 4 + count_bits(numbers.len());
+    match mode {
+        Mode::Plain => plain_header_bits(numbers.len(), determine_bits_per_number(numbers)),
+        Mode::FrameOfReference => extended + 9 + 4,
+        Mode::Arithmetic => extended + 9 + 1 + 9,
+        Mode::Outliers => {
+            let width = outlier_width(numbers);
+            extended + 4 + count_bits(numbers.iter().filter(|&&num| bit_width(num) > width).count())
+        }
+        Mode::VariableBlocks => extended,
+        Mode::Positions => extended + count_bits(position_groups(numbers).len() - 1) + 9,
+    }
+}
+
+/// Точная длина записи `mode` в битах до дополнения, посчитанная без кодирования.
+fn encoded_bits(numbers: &[u16], mode: Mode) -> usize {
+    let header = header_bits(numbers, mode);
+    let count = numbers.len();
+    header
+        + match mode {
+            Mode::Plain => count * determine_bits_per_number(numbers) as usize,
+            Mode::FrameOfReference => count * bits_per_value(numbers, mode) as usize,
+            Mode::Arithmetic => 0,
+            Mode::Outliers => {
+                let width = outlier_width(numbers);
+                let outliers = numbers.iter().filter(|&&num| bit_width(num) > width).count();
+                outliers * 19 + count * width as usize
+            }
+            Mode::VariableBlocks => block_ranges(numbers)
+                .map(|block| {
+                    let width = bit_width(block.iter().max().unwrap() - block.iter().min().unwrap()) as usize;
+                    block_len_bits(count) + 9 + 4 + block.len() * width
+                })
+                .sum(),
+            Mode::Positions => {
+                let groups = position_groups(numbers);
+                groups[..groups.len() - 1]
+                    .iter()
+                    .map(|(_, positions)| 9 + bit_width(count as u16) as usize + 4 + positions.len() * gap_width(positions) as usize)
+                    .sum()
+            }
+        }
+}
+
+/// Как `encoded_bits`, но для `serialize_blocked` с блоками по `block_size` чисел.
+fn blocked_bits(numbers: &[u16], block_size: usize) -> usize {
+    let header = count_bits(0) + 4 + count_bits(numbers.len()) + 6;
+    header
+        + numbers
+            .chunks(block_size)
+            .map(|block| 9 + 4 + block.len() * bit_width(block.iter().max().unwrap() - block.iter().min().unwrap()) as usize)
+            .sum::<usize>()
+}
+
+/// Записывает `serialize(numbers)` в начало `out` и возвращает число записанных байт.
+/// Ошибки `estimated_len` возвращаются; паникует, если `out` короче `estimated_len(numbers)`.
+pub fn serialize_into(numbers: &[u16], out: &mut [u8]) -> Result<usize, SerializeError> {
+    let len = estimated_len(numbers)?;
+    assert!(out.len() >= len, "Буфер короче estimated_len: {} < {}", out.len(), len);
+    Ok(write_text(&to_bitstring(numbers), &STANDARD_ALPHABET, out))
+}
+
+/// Кодирует прямо в отображённый в память файл, размер которого заранее задаётся `estimated_len`.
+/// Вход проверяется до создания файла: ошибка `validate` — `InvalidInput`, файл не трогается.
+/// Вместо `memmap2` из запроса отображение сделано вручную через `libc::mmap` (mmap, msync,
+/// munmap), поэтому функция есть только на unix.
+#[cfg(all(feature = "mmap", unix))]
+pub fn serialize_to_mmap(numbers: &[u16], path: &std::path::Path) -> std::io::Result<usize> {
+    use std::os::fd::AsRawFd;
+
+    let invalid_input = |err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err);
+    let len = estimated_len(numbers).map_err(invalid_input)?;
+    let file = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+    file.set_len(len as u64)?;
+
+    // SAFETY: файл открыт на чтение и запись и имеет длину `len > 0`; отображение снимается
+    // до выхода из функции, и других ссылок на эту память нет.
+    unsafe {
+        let ptr = libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        let written = serialize_into(numbers, std::slice::from_raw_parts_mut(ptr as *mut u8, len));
+        let synced = libc::msync(ptr, len, libc::MS_SYNC);
+        let error = std::io::Error::last_os_error();
+        libc::munmap(ptr, len);
+        if synced != 0 {
+            return Err(error);
+        }
+        written.map_err(invalid_input)
+    }
+}
+
+/// Та же битовая строка, что и в `serialize`, но сгруппированная по 8 бит в байты.
+pub fn serialize_bytes(numbers: &[u16]) -> Vec<u8> {
+    bitstring_to_bytes(to_bitstring(numbers))
+}
+
+/// Вид результата `serialize_auto_encoding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Строка `serialize`.
+    Text,
+    /// Байты `serialize_bytes`.
+    Binary,
+}
+
+/// Запись в том виде, который выбрал `Target`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Encoded {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl Encoded {
+    /// Декодирует запись парным декодером: `deserialize` или `deserialize_bytes`.
+    pub fn decode(&self) -> Result<Vec<u16>, DeserializeError> {
+        match self {
+            Encoded::Text(s) => deserialize(s),
+            Encoded::Binary(bytes) => deserialize_bytes(bytes),
+        }
+    }
+}
+
+/// `serialize` или `serialize_bytes` в зависимости от `target`, когда вид вывода известен
+/// только во время выполнения.
+pub fn serialize_auto_encoding(numbers: &[u16], target: Target) -> Encoded {
+    match target {
+        Target::Text => Encoded::Text(serialize(numbers)),
+        Target::Binary => Encoded::Binary(serialize_bytes(numbers)),
+    }
+}
+
+/// Тело упакованного поля protobuf `repeated uint32`: варинты подряд, без тега и длины.
+/// Диапазон 1-300 здесь не проверяется — подходит любой `u16`.
+pub fn serialize_protobuf_packed(numbers: &[u16]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(numbers.len() * 2);
+    for &num in numbers {
+        let mut value = num as u32;
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+    out
+}
+
+/// Запутывание для беглого просмотра, не шифрование: байты `serialize_bytes` переставляются
+/// перестановкой, заданной ключом, и кодируются в base64. Размер не меняется — запись
+/// занимает столько же символов, сколько base64 от `serialize_bytes`.
+pub fn serialize_with_key(numbers: &[u16], key: u64) -> String {
+    let bytes = serialize_bytes(numbers);
+
+    let permutation = key_permutation(bytes.len(), key);
+    let mut bitstr = String::with_capacity(bytes.len() * 8);
+    for &from in &permutation {
+        push_bits(&mut bitstr, bytes[from] as usize, 8);
+    }
+    bitstring_to_base64(bitstr)
+}
+
+/// Обратное к `serialize_with_key`. Контрольной суммы нет, так что другой ключ даёт ошибку
+/// декодирования или другой список, но не исходный.
+pub fn deserialize_with_key(s: &str, key: u64) -> Result<Vec<u16>, DeserializeError> {
+    let mut reader = BitReader::base64(s);
+    let len = reader.len() / 8;
+    let permutation = key_permutation(len, key);
+    let mut bytes = vec![0; len];
+    for &to in &permutation {
+        bytes[to] = reader.read(8)? as u8;
+    }
+    deserialize_bytes(&bytes)
+}
+
+/// Перестановка Фишера — Йетса на splitmix64 от `key`: своя, чтобы не зависеть от версии `rand`.
+fn key_permutation(len: usize, key: u64) -> Vec<usize> {
+    let mut state = key;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ z >> 30).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ z >> 27).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ z >> 31
+    };
+    let mut permutation: Vec<usize> = (0..len).collect();
+    for i in (1..len).rev() {
+        permutation.swap(i, (next() % (i as u64 + 1)) as usize);
+    }
+    permutation
+}
+
+fn bitstring_to_bytes(mut bitstr: String) -> Vec<u8> {
+    let padding = (8 - (bitstr.len() % 8)) % 8;
+    bitstr += &"0".repeat(padding);
+
+    bitstr
+        .as_bytes()
+        .chunks(8)
+        .map(|chunk| u8::from_str_radix(std::str::from_utf8(chunk).unwrap(), 2).unwrap())
+        .collect()
+}
+
+/// Раздельная форма: заголовок и числа дополняются до целого байта каждый, чтобы их можно
+/// было хранить отдельно. Обычные `serialize`/`serialize_bytes` сохраняют компактную раскладку.
+pub fn serialize_split(numbers: &[u16]) -> (Vec<u8>, Vec<u8>) {
+    assert_len(numbers);
+    assert_in_range(numbers);
+    let width = determine_bits_per_number(numbers);
+
+    let mut header = String::new();
+    push_plain_header(&mut header, numbers.len(), width);
+
+    let mut payload = String::new();
+    for &num in numbers {
+        push_bits(&mut payload, num as usize, width as usize);
+    }
+
+    (bitstring_to_bytes(header), bitstring_to_bytes(payload))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeserializeError {
+    InvalidChar { ch: char, at_char: usize },
+    InvalidGroup { at_char: usize },
+    InvalidMode { at_bit: usize },
+    UnexpectedEof { needed: usize, available: usize },
+    TooManyElements { count: usize, max: usize },
+    TrailingData { at_bit: usize },
+    IndexOutOfBounds { index: usize, len: usize },
+    InvalidCount { count: usize, at_bit: usize },
+    InvalidVarint { at_byte: usize },
+    LimitExceeded { max_bytes: usize },
+    InvalidDataUri,
+    ValueOutOfRange { value: u16 },
+    Io(std::io::ErrorKind),
+}
+
+impl std::fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeserializeError::InvalidChar { ch, at_char } => {
+                write!(f, "Invalid char {:?} at char {}", ch, at_char)
+            }
+            DeserializeError::InvalidGroup { at_char } => {
+                write!(f, "Invalid base45 group at char {}", at_char)
+            }
+            DeserializeError::InvalidMode { at_bit } => write!(f, "Invalid mode at bit {}", at_bit),
+            DeserializeError::UnexpectedEof { needed, available } => {
+                write!(f, "Unexpected end of input: needed {} bits, {} available", needed, available)
+            }
+            DeserializeError::TooManyElements { count, max } => {
+                write!(f, "Declared count {} exceeds the limit of {}", count, max)
+            }
+            DeserializeError::TrailingData { at_bit } => {
+                write!(f, "Unexpected data after the last value at bit {}", at_bit)
+            }
+            DeserializeError::InvalidCount { count, at_bit } => {
+                write!(f, "Count {} at bit {} must use the short 6-bit form", count, at_bit)
+            }
+            DeserializeError::InvalidVarint { at_byte } => {
+                write!(f, "Truncated or too long varint at byte {}", at_byte)
+            }
+            DeserializeError::LimitExceeded { max_bytes } => {
+                write!(f, "Record is not complete within the limit of {} bytes", max_bytes)
+            }
+            DeserializeError::InvalidDataUri => write!(f, "Expected a {:?} data URI", DATA_URI_PREFIX),
+            DeserializeError::ValueOutOfRange { value } => write!(f, "Value {} is outside 1-300", value),
+            DeserializeError::Io(kind) => write!(f, "I/O error: {}", kind),
+            DeserializeError::IndexOutOfBounds { index, len } => {
+                write!(f, "Index {} is out of bounds for {} values", index, len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+/// Читает биты старшими вперёд из последовательности символов по `symbol_bits` бит каждый.
+struct BitReader<'a> {
+    symbols: &'a [u8],
+    symbol_bits: usize,
+    /// Таблица обратного поиска алфавита; `None` — символы уже являются байтами.
+    lookup: Option<&'a [u8; 256]>,
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn base64(s: &'a str) -> Self {
+        Self::text(s, &STANDARD_ALPHABET)
+    }
+
+    /// Символы читаются лениво, поэтому всё, что лежит после записи, не проверяется.
+    fn text(s: &'a str, alphabet: &'a Alphabet) -> Self {
+        Self::ascii(s.as_bytes(), alphabet)
+    }
+
+    /// Как `text`, но для байт, которые ещё не проверены как UTF-8.
+    fn ascii(bytes: &'a [u8], alphabet: &'a Alphabet) -> Self {
+        BitReader { symbols: bytes, symbol_bits: 6, lookup: Some(&alphabet.lookup), pos: 0 }
+    }
+
+    fn bytes(bytes: &'a [u8]) -> Self {
+        BitReader { symbols: bytes, symbol_bits: 8, lookup: None, pos: 0 }
+    }
+
+    fn len(&self) -> usize {
+        self.symbols.len() * self.symbol_bits
+    }
+
+    fn read(&mut self, n: usize) -> Result<usize, DeserializeError> {
+        let available = self.len() - self.pos.min(self.len());
+        if n > available {
+            return Err(DeserializeError::UnexpectedEof { needed: n, available });
+        }
+        let mut value = 0;
+        for _ in 0..n {
+            let symbol = self.symbol(self.pos / self.symbol_bits)?;
+            let shift = self.symbol_bits - 1 - self.pos % self.symbol_bits;
+            value = value << 1 | (symbol >> shift & 1) as usize;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    fn symbol(&self, at_char: usize) -> Result<u8, DeserializeError> {
+        let raw = self.symbols[at_char];
+        match self.lookup {
+            Some(lookup) if lookup[raw as usize] == NOT_IN_ALPHABET => {
+                // Все предыдущие символы из ASCII-алфавита, значит `at_char` — граница символа.
+                let ch = std::str::from_utf8(&self.symbols[at_char..])
+                    .ok()
+                    .and_then(|rest| rest.chars().next())
+                    .unwrap_or(raw as char);
+                Err(DeserializeError::InvalidChar { ch, at_char })
+            }
+            Some(lookup) => Ok(lookup[raw as usize]),
+            None => Ok(raw),
+        }
+    }
+
+    /// Сколько бит читать словом для чисел ширины `width`: наибольшее кратное `width`,
+    /// помещающееся в `u64`.
+    fn word_bits(width: usize) -> Option<usize> {
+        (width > 0).then(|| 64 / width * width)
+    }
+
+    /// Читает `bits` (не больше 64) бит с текущей позиции: покрывающие их символы целиком
+    /// попадают в `u128`, лишние биты по краям отбрасываются. При ошибке позиция не меняется,
+    /// чтобы побитовое чтение сообщило о ней как обычно.
+    fn read_word(&mut self, bits: usize) -> Option<u64> {
+        let end = self.pos + bits;
+        if end > self.len() {
+            return None;
+        }
+        let first = self.pos / self.symbol_bits;
+        let last = end.div_ceil(self.symbol_bits);
+        let mut acc = 0u128;
+        for at_char in first..last {
+            acc = acc << self.symbol_bits | self.symbol(at_char).ok()? as u128;
+        }
+        let trailing = last * self.symbol_bits - end;
+        self.pos = end;
+        Some((acc >> trailing) as u64 & (u64::MAX >> (64 - bits)))
+    }
+
+    /// Длины меньше 64 кодер всегда пишет коротким полем, поэтому длинное поле с такой длиной
+    /// (в том числе нулевой — это не расширенный заголовок) считается повреждением.
+    fn read_count(&mut self) -> Result<usize, DeserializeError> {
+        let at_bit = self.pos;
+        if self.read(1)? == 0 {
+            return self.read(6);
+        }
+        let count = self.read(10)?;
+        if count < 64 {
+            return Err(DeserializeError::InvalidCount { count, at_bit });
+        }
+        Ok(count)
+    }
+
+    /// Переходит к началу следующего символа (пропускает биты выравнивания).
+    fn align(&mut self) {
+        self.pos = self.pos.div_ceil(self.symbol_bits) * self.symbol_bits;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.len()
+    }
+
+    /// Остались ли только нулевые биты дополнения до конца текущего символа.
+    fn at_padding(&mut self) -> Result<bool, DeserializeError> {
+        let rest = self.len() - self.pos.min(self.len());
+        Ok(rest < self.symbol_bits && self.read(rest)? == 0)
+    }
+}
+
+/// Потоковое декодирование: заголовок читается сразу, числа — по мере обхода.
+struct Values<'r, 'a> {
+    reader: &'r mut BitReader<'a>,
+    state: ValuesState,
+}
+
+/// Состояние `Values` без ссылки на вход: `StreamDecoder` хранит его между кусками.
+#[derive(Debug, Clone)]
+struct ValuesState {
+    remaining: usize,
+    bits_per_number: usize,
+    base: u16,
+    /// Ненулевой для `MODE_BLOCKED`: минимум и ширина читаются заново каждые `block_size` чисел.
+    block_size: usize,
+    block_left: usize,
+    /// Шаг для `MODE_ARITHMETIC`: числа не читаются, а вычисляются от `base`.
+    step: Option<i16>,
+    /// Быстрый путь: размер слова (`BitReader::word_bits`) и ещё не выданные числа
+    /// последнего прочитанного слова, старшие первыми.
+    word_bits: Option<usize>,
+    word: u64,
+    word_values: usize,
+    /// Выбросы `MODE_OUTLIERS` как пары «`remaining` на их месте, число», ближайший — последним.
+    outliers: Vec<(usize, u16)>,
+    /// `MODE_DELTA`: предыдущее выданное число, к которому прибавляется следующая разность.
+    delta: bool,
+    previous: Option<u16>,
+    block_len_bits: Option<usize>,
+}
+
+/// Параметры декодирования недоверенного ввода.
+#[derive(Debug, Clone, Copy)]
+pub struct DeserializeConfig {
+    /// Наибольшая объявленная в заголовке длина; проверяется до выделения памяти под числа.
+    pub max_decode_count: usize,
+    /// После последнего числа допускается только нулевое дополнение до границы символа;
+    /// иначе — `TrailingData` (например, если длина в заголовке меньше числа записанных значений).
+    pub strict: bool,
+}
+
+impl Default for DeserializeConfig {
+    fn default() -> Self {
+        DeserializeConfig { max_decode_count: 1023, strict: false }
+    }
+}
+
+#[derive(Default)]
+struct Header {
+    count: usize,
+    bits_per_number: usize,
+    base: u16,
+    block_size: usize,
+    step: Option<i16>,
+    /// Выбросы `MODE_OUTLIERS`: номер и число, по возрастанию номеров.
+    outliers: Vec<(usize, u16)>,
+    /// `MODE_DELTA`: `base` — первое число, дальше читаются разности.
+    delta: bool,
+    /// `MODE_VARIABLE_BLOCKS`: ширина поля длины блока.
+    block_len_bits: Option<usize>,
+}
+
+fn read_header(reader: &mut BitReader) -> Result<Header, DeserializeError> {
+    let count = reader.read_count()?;
+
+    if count == 0 {
+        let at_bit = reader.pos;
+        return match reader.read(4)? {
+            MODE_FRAME_OF_REFERENCE => {
+                let count = reader.read_count()?;
+                let base = reader.read(9)? as u16;
+                let bits_per_number = reader.read(4)?;
+                Ok(Header { count, bits_per_number, base, ..Header::default() })
+            }
+            MODE_WIDTH => {
+                let count = reader.read_count()?;
+                let bits_per_number = reader.read(4)?;
+                Ok(Header { count, bits_per_number, ..Header::default() })
+            }
+            MODE_BLOCKED => {
+                let count = reader.read_count()?;
+                let block_size = reader.read(6)? + 1;
+                Ok(Header { count, block_size, ..Header::default() })
+            }
+            MODE_ARITHMETIC => {
+                let count = reader.read_count()?;
+                let base = reader.read(9)? as u16;
+                let negative = reader.read(1)? == 1;
+                let step = reader.read(9)? as i16;
+                let step = if negative { -step } else { step };
+                Ok(Header { count, base, step: Some(step), ..Header::default() })
+            }
+            MODE_OUTLIERS => {
+                let count = reader.read_count()?;
+                let bits_per_number = reader.read(4)?;
+                let outlier_count = reader.read_count()?;
+                if outlier_count > count {
+                    return Err(DeserializeError::TooManyElements { count: outlier_count, max: count });
+                }
+                let mut outliers: Vec<(usize, u16)> = Vec::with_capacity(outlier_count);
+                for _ in 0..outlier_count {
+                    let at_bit = reader.pos;
+                    let index = reader.read(10)?;
+                    if index >= count {
+                        return Err(DeserializeError::IndexOutOfBounds { index, len: count });
+                    }
+                    // Номера строго возрастают, иначе выброс на месте не определён.
+                    if outliers.last().is_some_and(|&(prev, _)| index <= prev) {
+                        return Err(DeserializeError::InvalidMode { at_bit });
+                    }
+                    outliers.push((index, reader.read(9)? as u16));
+                }
+                Ok(Header { count, bits_per_number, outliers, ..Header::default() })
+            }
+            MODE_POSITIONS => {
+                let count = reader.read_count()?;
+                let explicit = reader.read_count()?;
+                let base = reader.read(9)? as u16;
+                // Позиции всех значений становятся выбросами поверх заполняющего числа ширины 0.
+                let mut outliers = Vec::new();
+                for _ in 0..explicit {
+                    let value = reader.read(9)? as u16;
+                    let positions = reader.read(bit_width(count as u16) as usize)?;
+                    let gap_bits = reader.read(4)?;
+                    let mut next = 0;
+                    for _ in 0..positions {
+                        let index = next + reader.read(gap_bits)?;
+                        if index >= count {
+                            return Err(DeserializeError::IndexOutOfBounds { index, len: count });
+                        }
+                        if outliers.len() == count {
+                            return Err(DeserializeError::TooManyElements { count: count + 1, max: count });
+                        }
+                        outliers.push((index, value));
+                        next = index + 1;
+                    }
+                }
+                outliers.sort_unstable();
+                // Одна позиция у двух значений.
+                if outliers.windows(2).any(|w| w[0].0 == w[1].0) {
+                    return Err(DeserializeError::InvalidMode { at_bit });
+                }
+                Ok(Header { count, base, outliers, ..Header::default() })
+            }
+            MODE_VARIABLE_BLOCKS => {
+                let count = reader.read_count()?;
+                Ok(Header { count, block_len_bits: Some(block_len_bits(count)), ..Header::default() })
+            }
+            MODE_DELTA => {
+                let count = reader.read_count()?;
+                let base = reader.read(9)? as u16;
+                let bits_per_number = reader.read(4)?;
+                Ok(Header { count, bits_per_number, base, delta: true, ..Header::default() })
+            }
+            // Числа с метками читает только `deserialize_tagged`.
+            _ => Err(DeserializeError::InvalidMode { at_bit }),
+        };
+    }
+
+    let bits_per_number = match reader.read(2)? {
+        0b00 => 4,
+        0b01 => 7,
+        0b10 => 9,
+        _ => 12,
+    };
+
+    Ok(Header { count, bits_per_number, ..Header::default() })
+}
+
+impl<'r, 'a> Values<'r, 'a> {
+    fn new(reader: &'r mut BitReader<'a>) -> Result<Self, DeserializeError> {
+        Self::with_config(reader, &DeserializeConfig::default())
+    }
+
+    fn with_config(reader: &'r mut BitReader<'a>, config: &DeserializeConfig) -> Result<Self, DeserializeError> {
+        let header = read_header(reader)?;
+        Self::from_header(reader, header, config)
+    }
+
+    fn from_header(reader: &'r mut BitReader<'a>, header: Header, config: &DeserializeConfig) -> Result<Self, DeserializeError> {
+        Ok(Values { reader, state: ValuesState::from_header(header, config)? })
+    }
+}
+
+impl Iterator for Values<'_, '_> {
+    type Item = Result<u16, DeserializeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.state.next(self.reader)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.state.remaining))
+    }
+}
+
+impl ValuesState {
+    fn from_header(header: Header, config: &DeserializeConfig) -> Result<Self, DeserializeError> {
+        if header.count > config.max_decode_count {
+            return Err(DeserializeError::TooManyElements { count: header.count, max: config.max_decode_count });
+        }
+        let blocked = header.block_size > 0 || header.block_len_bits.is_some();
+        let word_bits = if blocked || header.delta { None } else { BitReader::word_bits(header.bits_per_number) };
+        Ok(ValuesState {
+            remaining: header.count,
+            bits_per_number: header.bits_per_number,
+            base: header.base,
+            block_size: header.block_size,
+            block_left: 0,
+            step: header.step,
+            word_bits,
+            word: 0,
+            word_values: 0,
+            outliers: header.outliers.iter().rev().map(|&(index, num)| (header.count - index, num)).collect(),
+            delta: header.delta,
+            previous: None,
+            block_len_bits: header.block_len_bits,
+        })
+    }
+
+    /// Число из слова `u64`, если в записи хватает чисел на всё слово и оно прочиталось
+    /// без ошибок; иначе `None` — тогда число читается побитово.
+    fn next_from_word(&mut self, reader: &mut BitReader) -> Option<usize> {
+        let width = self.bits_per_number;
+        if self.word_values == 0 {
+            let bits = self.word_bits?;
+            // `remaining` уже без текущего числа.
+            if self.remaining + 1 < bits / width {
+                return None;
+            }
+            self.word = reader.read_word(bits)?;
+            self.word_values = bits / width;
+        }
+        self.word_values -= 1;
+        Some((self.word >> (self.word_values * width)) as usize & ((1 << width) - 1))
+    }
+
+    fn read_block_header(&mut self, reader: &mut BitReader) -> Result<(), DeserializeError> {
+        self.block_left = match self.block_len_bits {
+            Some(bits) => reader.read(bits)? + 1,
+            None => self.block_size,
+        };
+        self.base = reader.read(9)? as u16;
+        self.bits_per_number = reader.read(4)?;
+        Ok(())
+    }
+
+    fn next(&mut self, reader: &mut BitReader) -> Option<Result<u16, DeserializeError>> {
+        // Нуль основной части на месте выброса всё равно читается.
+        let num = self.next_value(reader)?;
+        Some(match self.outliers.last() {
+            Some(&(at, outlier)) if num.is_ok() && at == self.remaining + 1 => {
+                self.outliers.pop();
+                Ok(outlier)
+            }
+            _ => num,
+        })
+    }
+
+    /// Как `next`, но при ошибке (в том числе нехватке бит) позиция и состояние возвращаются
+    /// к началу числа, чтобы дочитать его, когда придут следующие символы.
+    fn next_or_rollback(&mut self, reader: &mut BitReader) -> Option<Result<u16, DeserializeError>> {
+        let saved = (reader.pos, self.remaining, self.base, self.bits_per_number, self.block_left, self.previous);
+        let num = self.next(reader);
+        if let Some(Err(_)) = num {
+            (reader.pos, self.remaining, self.base, self.bits_per_number, self.block_left, self.previous) = saved;
+        }
+        num
+    }
+
+    fn next_value(&mut self, reader: &mut BitReader) -> Option<Result<u16, DeserializeError>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        if let Some(step) = self.step {
+            let num = self.base;
+            self.base = self.base.wrapping_add_signed(step);
+            return Some(Ok(num));
+        }
+        if self.delta {
+            let num = match self.previous {
+                None => Ok(self.base),
+                Some(prev) => reader.read(self.bits_per_number).map(|delta| prev.wrapping_add(delta as u16)),
+            };
+            self.previous = num.as_ref().ok().copied();
+            return Some(num);
+        }
+        if self.block_size > 0 || self.block_len_bits.is_some() {
+            if self.block_left == 0
+                && let Err(e) = self.read_block_header(reader)
+            {
+                return Some(Err(e));
+            }
+            self.block_left -= 1;
+        } else if let Some(num) = self.next_from_word(reader) {
+            return Some(Ok(self.base + num as u16));
+        }
+        Some(reader.read(self.bits_per_number).map(|num| self.base + num as u16))
+    }
+}
+
+/// Режим записи по её заголовку, без чтения чисел: `Plain` (включая явную ширину
+/// `serialize_with_width`), FOR, прогрессия или исключения. Для записей других форматов
+/// (метки, блоки, частоты, числа со знаком, разности) — `None`.
+pub fn inspect(s: &str) -> Result<Option<Mode>, DeserializeError> {
+    let mut reader = BitReader::base64(s);
+    if reader.read_count()? != 0 {
+        return Ok(Some(Mode::Plain));
+    }
+    let at_bit = reader.pos;
+    Ok(match reader.read(4)? {
+        MODE_WIDTH => Some(Mode::Plain),
+        MODE_FRAME_OF_REFERENCE => Some(Mode::FrameOfReference),
+        MODE_ARITHMETIC => Some(Mode::Arithmetic),
+        MODE_OUTLIERS => Some(Mode::Outliers),
+        MODE_VARIABLE_BLOCKS => Some(Mode::VariableBlocks),
+        MODE_POSITIONS => Some(Mode::Positions),
+        MODE_TAGGED | MODE_BLOCKED | MODE_COUNTS | MODE_ZIGZAG | MODE_DELTA => None,
+        _ => return Err(DeserializeError::InvalidMode { at_bit }),
+    })
+}
+
+/// Объявленная длина записи: читаются только поля длины (и код режима расширенного заголовка),
+/// числа и остальной заголовок не проверяются. Для записи частот — число пар.
+pub fn peek_count(s: &str) -> Result<usize, DeserializeError> {
+    let mut reader = BitReader::base64(s);
+    match reader.read_count()? {
+        0 => {
+            let at_bit = reader.pos;
+            if reader.read(4)? > MODE_POSITIONS {
+                return Err(DeserializeError::InvalidMode { at_bit });
+            }
+            reader.read_count()
+        }
+        count => Ok(count),
+    }
+}
+
+pub fn deserialize(s: &str) -> Result<Vec<u16>, DeserializeError> {
+    deserialize_bytes_str(s.as_bytes())
+}
+
+/// Как `deserialize`, но принимает байты текста (например, буфер сокета) без проверки UTF-8:
+/// каждый байт — символ алфавита, любой другой байт — `InvalidChar`.
+pub fn deserialize_bytes_str(bytes: &[u8]) -> Result<Vec<u16>, DeserializeError> {
+    decode_with_config(&mut BitReader::ascii(bytes, &STANDARD_ALPHABET), &DeserializeConfig::default())
+}
+
+pub fn deserialize_with_config(s: &str, config: &DeserializeConfig) -> Result<Vec<u16>, DeserializeError> {
+    decode_with_config(&mut BitReader::base64(s), config)
+}
+
+fn decode_with_config(reader: &mut BitReader, config: &DeserializeConfig) -> Result<Vec<u16>, DeserializeError> {
+    let numbers = collect_values(Values::with_config(reader, config)?)?;
+    let at_bit = reader.pos;
+    if config.strict && !reader.at_padding()? {
+        return Err(DeserializeError::TrailingData { at_bit });
+    }
+    Ok(numbers)
+}
+
+/// Декодирует и сразу преобразует каждое число в `T`, без промежуточного `Vec<u16>`.
+pub fn deserialize_as<T: From<u16>>(s: &str) -> Result<Vec<T>, DeserializeError> {
+    let mut reader = BitReader::base64(s);
+    collect_values(Values::new(&mut reader)?)
+}
+
+/// Дописывает числа записи в конец `buf` и возвращает их количество. Ёмкость буфера
+/// переиспользуется: если её хватает, декодирование не выделяет память. При ошибке `buf`
+/// возвращается к исходной длине.
+pub fn deserialize_into(s: &str, buf: &mut Vec<u16>) -> Result<usize, DeserializeError> {
+    let start = buf.len();
+    let mut reader = BitReader::base64(s);
+    let result = Values::new(&mut reader).and_then(|mut values| {
+        buf.reserve(values.state.remaining);
+        values.try_for_each(|num| num.map(|num| buf.push(num)))
+    });
+    match result {
+        Ok(()) => Ok(buf.len() - start),
+        Err(err) => {
+            buf.truncate(start);
+            Err(err)
+        }
+    }
+}
+
+fn collect_values<T: From<u16>>(values: Values) -> Result<Vec<T>, DeserializeError> {
+    let mut numbers = Vec::with_capacity(values.state.remaining);
+    for num in values {
+        numbers.push(T::from(num?));
+    }
+    Ok(numbers)
+}
+
+pub fn deserialize_with_alphabet(s: &str, alphabet: &Alphabet) -> Result<Vec<u16>, DeserializeError> {
+    let mut reader = BitReader::text(s, alphabet);
+    Values::new(&mut reader)?.collect()
+}
+
+/// Декодирует не больше `n` первых чисел; остальная часть записи не читается.
+pub fn deserialize_take(s: &str, n: usize) -> Result<Vec<u16>, DeserializeError> {
+    let mut reader = BitReader::base64(s);
+    Values::new(&mut reader)?.take(n).collect()
+}
+
+/// Декодирует запись в начале `s` и возвращает числа вместе с количеством прочитанных символов.
+/// Длина записи определяется заголовком: `serialize` дополняет биты нулями до целого символа.
+pub fn deserialize_prefix(s: &str) -> Result<(Vec<u16>, usize), DeserializeError> {
+    let mut reader = BitReader::base64(s);
+    let numbers = Values::new(&mut reader)?.collect::<Result<_, _>>()?;
+    reader.align();
+    Ok((numbers, reader.pos / reader.symbol_bits))
+}
+
+/// Как `deserialize_prefix`, но возвращает непрочитанный остаток строки.
+pub fn deserialize_with_tail(s: &str) -> Result<(Vec<u16>, &str), DeserializeError> {
+    let (numbers, consumed) = deserialize_prefix(s)?;
+    Ok((numbers, &s[consumed..]))
+}
+
+/// Декодирует несколько записей `serialize`, склеенных без разделителя.
+pub fn decode_all_concatenated(s: &str) -> Result<Vec<Vec<u16>>, DeserializeError> {
+    let mut reader = BitReader::base64(s);
+    let mut records = Vec::new();
+    while !reader.is_empty() {
+        records.push(Values::new(&mut reader)?.collect::<Result<_, _>>()?);
+        reader.align();
+    }
+    Ok(records)
+}
+
+pub fn deserialize_bytes(bytes: &[u8]) -> Result<Vec<u16>, DeserializeError> {
+    let mut reader = BitReader::bytes(bytes);
+    Values::new(&mut reader)?.collect()
+}
+
+/// Обратное к `serialize_protobuf_packed`. Варинт, который обрывается или не помещается
+/// в `u16`, — ошибка `InvalidVarint` с номером его первого байта.
+pub fn deserialize_protobuf_packed(bytes: &[u8]) -> Result<Vec<u16>, DeserializeError> {
+    let mut numbers = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let at_byte = pos;
+        let mut value = 0u32;
+        let mut shift = 0;
+        loop {
+            let &byte = bytes.get(pos).ok_or(DeserializeError::InvalidVarint { at_byte })?;
+            pos += 1;
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            if shift > 14 {
+                return Err(DeserializeError::InvalidVarint { at_byte });
+            }
+        }
+        numbers.push(u16::try_from(value).map_err(|_| DeserializeError::InvalidVarint { at_byte })?);
+    }
+    Ok(numbers)
+}
+
+/// Декодер записи, которая приходит частями (например, тело chunked-ответа HTTP): `feed`
+/// принимает очередной кусок и возвращает числа, которые в нём завершились.
+///
+/// Между вызовами хранятся накопленные символы записи (не больше нескольких килобайт), позиция
+/// чтения и состояние режима (`ValuesState`), так что каждый символ читается один раз. Пока
+/// заголовок не пришёл целиком, он перечитывается с начала; для выбросов и позиций это вся
+/// таблица режима. Неполное число дочитывается при следующем `feed`.
+#[derive(Debug, Clone, Default)]
+pub struct StreamDecoder {
+    buffer: Vec<u8>,
+    pos: usize,
+    state: Option<ValuesState>,
+}
+
+impl StreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Нехватка данных — не ошибка: числа, которым ещё не хватает бит, придут со следующими
+    /// кусками. Остальные ошибки (`InvalidChar`, `InvalidMode`, ...) возвращаются сразу.
+    /// Байты после конца записи не читаются и не сохраняются.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Vec<u16>, DeserializeError> {
+        if self.is_finished() {
+            return Ok(Vec::new());
+        }
+        self.buffer.extend_from_slice(bytes);
+
+        let mut reader = BitReader::ascii(&self.buffer, &STANDARD_ALPHABET);
+        reader.pos = self.pos;
+        let state = match &mut self.state {
+            Some(state) => state,
+            None => match read_header(&mut reader).and_then(|header| ValuesState::from_header(header, &DeserializeConfig::default())) {
+                Err(DeserializeError::UnexpectedEof { .. }) => return Ok(Vec::new()),
+                state => self.state.insert(state?),
+            },
+        };
+
+        let mut numbers = Vec::new();
+        while let Some(num) = state.next_or_rollback(&mut reader) {
+            match num {
+                Ok(num) => numbers.push(num),
+                Err(DeserializeError::UnexpectedEof { .. }) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        self.pos = reader.pos;
+        if self.is_finished() {
+            self.buffer = Vec::new();
+        }
+        Ok(numbers)
+    }
+
+    /// Все объявленные в заголовке числа выданы.
+    pub fn is_finished(&self) -> bool {
+        self.state.as_ref().is_some_and(|state| state.remaining == 0)
+    }
+
+    /// Ошибка незавершённой записи, если кусков больше не будет: то, на чём остановился `feed`.
+    fn eof_error(&self) -> DeserializeError {
+        let mut reader = BitReader::ascii(&self.buffer, &STANDARD_ALPHABET);
+        reader.pos = self.pos;
+        let error = match self.state.clone() {
+            Some(mut state) => state.next(&mut reader).and_then(Result::err),
+            None => read_header(&mut reader).err(),
+        };
+        error.unwrap_or(DeserializeError::UnexpectedEof { needed: 1, available: 0 })
+    }
+}
+
+/// Читает запись из `r` порциями, пока их не хватит на объявленную длину; после записи
+/// поток больше не читается. Если запись не закончилась в первых `max_bytes` байтах, чтение
+/// прекращается с `LimitExceeded`, так что медленный или бесконечный поток не держит декодер.
+pub fn deserialize_from<R: std::io::Read>(mut r: R, max_bytes: usize) -> Result<Vec<u16>, DeserializeError> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 256];
+    loop {
+        let mut reader = BitReader::ascii(&buffer, &STANDARD_ALPHABET);
+        let eof = match Values::new(&mut reader).and_then(|values| values.collect()) {
+            Err(eof @ DeserializeError::UnexpectedEof { .. }) => eof,
+            result => return result,
+        };
+        if buffer.len() >= max_bytes {
+            return Err(DeserializeError::LimitExceeded { max_bytes });
+        }
+
+        let want = (max_bytes - buffer.len()).min(chunk.len());
+        match r.read(&mut chunk[..want]) {
+            Ok(0) => return Err(eof),
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(DeserializeError::Io(e.kind())),
+        }
+    }
+}
+
+fn invalid_data(error: impl std::error::Error + Send + Sync + 'static) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+}
+
+/// Декодирует запись из `r` и пишет числа в `w` как массив `u16` little-endian. Вход читается
+/// порциями через `StreamDecoder`, и каждое число пишется, как только его биты пришли; после
+/// записи поток больше не читается. Возвращает число записанных чисел.
+pub fn transcode_to_raw<R: std::io::Read, W: std::io::Write>(mut r: R, mut w: W) -> std::io::Result<usize> {
+    let mut decoder = StreamDecoder::new();
+    let mut chunk = [0u8; 256];
+    let mut written = 0;
+    while !decoder.is_finished() {
+        let read = match r.read(&mut chunk) {
+            Ok(0) => return Err(invalid_data(decoder.eof_error())),
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for num in decoder.feed(&chunk[..read]).map_err(invalid_data)? {
+            w.write_all(&num.to_le_bytes())?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+/// Обратное к `transcode_to_raw`: читает массив `u16` little-endian и пишет его запись.
+/// Числа приходится накопить, потому что длина и ширина стоят в заголовке; вход проверяется
+/// `validate`, ошибки возвращаются как `InvalidData`.
+pub fn transcode_from_raw<R: std::io::Read, W: std::io::Write>(mut r: R, mut w: W) -> std::io::Result<usize> {
+    let mut raw = Vec::new();
+    r.read_to_end(&mut raw)?;
+    if raw.len() % 2 != 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "odd number of bytes in a u16 array"));
+    }
+    let numbers: Vec<u16> = raw.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+    validate(&numbers).map_err(invalid_data)?;
+    w.write_all(serialize(&numbers).as_bytes())?;
+    Ok(numbers.len())
+}
+
+/// Итог `serialize_stream`: сколько чисел и кадров записано и размеры, посчитанные по ходу.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StreamStats {
+    pub count: usize,
+    pub frames: usize,
+    pub trivial_len: usize,
+    pub serialized_len: usize,
+}
+
+impl StreamStats {
+    pub fn ratio(&self) -> f64 {
+        self.serialized_len as f64 / self.trivial_len as f64
+    }
+}
+
+/// Кодирует поток любой длины кадрами по 1023 числа (каждый — `serialize_best`) и пишет их
+/// в `w` подряд; читать обратно — `decode_all_concatenated`. В памяти держится только текущий
+/// кадр. Числа вне 1-300 — ошибка `InvalidData`, уже записанные кадры остаются в `w`.
+pub fn serialize_stream<I: IntoIterator<Item = u16>, W: std::io::Write>(iter: I, mut w: W) -> std::io::Result<StreamStats> {
+    let mut stats = StreamStats::default();
+    let mut frame = Vec::with_capacity(1023);
+    let mut iter = iter.into_iter().peekable();
+    while iter.peek().is_some() {
+        frame.clear();
+        frame.extend(iter.by_ref().take(1023));
+        validate(&frame).map_err(invalid_data)?;
+
+        let serialized = serialize_best(&frame);
+        w.write_all(serialized.as_bytes())?;
+        stats.trivial_len += frame.iter().map(|n| n.to_string().len() + 1).sum::<usize>();
+        stats.serialized_len += serialized.len();
+        stats.count += frame.len();
+        stats.frames += 1;
+    }
+    // Запятых на одну меньше, чем чисел.
+    stats.trivial_len = stats.trivial_len.saturating_sub(1);
+    Ok(stats)
+}
+
+/// Итератор `chunked_serialize`: по записи `serialize_best` на каждое окно.
+#[derive(Debug, Clone)]
+pub struct ChunkedSerialize<I> {
+    iter: I,
+    window: usize,
+}
+
+impl<I: Iterator<Item = u16>> Iterator for ChunkedSerialize<I> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let frame: Vec<u16> = self.iter.by_ref().take(self.window).collect();
+        (!frame.is_empty()).then(|| serialize_best(&frame))
+    }
+}
+
+/// Разбивает поток на окна по `window` чисел (последнее может быть короче) и кодирует каждое
+/// отдельной записью; склейку записей читает `decode_all_concatenated`. В отличие от
+/// `serialize_stream`, числа вне 1-300 вызывают панику, как в `serialize`.
+pub fn chunked_serialize<I: IntoIterator<Item = u16>>(iter: I, window: usize) -> ChunkedSerialize<I::IntoIter> {
+    assert!((1..=1023).contains(&window), "Размер окна должен быть от 1 до 1023, получено {}", window);
+    ChunkedSerialize { iter: iter.into_iter(), window }
+}
+
+/// Кодирует список любой длины кадрами по `max_per_frame` чисел, каждый — отдельная запись
+/// `serialize_best`. Размер кадра вне 1-1023 — ошибка `InvalidFrameSize`, числа вне 1-300 —
+/// `Invalid`, как в `validate`. Обратная операция — `deserialize_framed`.
+pub fn serialize_framed(numbers: &[u16], max_per_frame: usize) -> Result<Vec<String>, SerializeError> {
+    if !(1..=1023).contains(&max_per_frame) {
+        return Err(SerializeError::InvalidFrameSize(max_per_frame));
+    }
+    let violations: Vec<Violation> = numbers
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| !(1..=300).contains(*n))
+        .map(|(index, &value)| Violation::OutOfRange { index, value })
+        .collect();
+    if !violations.is_empty() {
+        return Err(SerializeError::Invalid(violations));
+    }
+    Ok(chunked_serialize(numbers.iter().copied(), max_per_frame).collect())
+}
+
+/// Декодирует кадры `serialize_framed` по порядку и склеивает числа в один список.
+pub fn deserialize_framed(frames: &[String]) -> Result<Vec<u16>, DeserializeError> {
+    let mut numbers = Vec::new();
+    for frame in frames {
+        deserialize_into(frame, &mut numbers)?;
+    }
+    Ok(numbers)
+}
+
+/// Единственная каноническая запись того же списка: декодирует и кодирует заново, так что
+/// биты дополнения обнуляются, а режим выбирается как в `serialize`. Числа вне 1-300
+/// (возможные в режимах с явной шириной и FOR) записываются с минимальной явной шириной,
+/// а пустой список — как `serialize_with_width(&[], 1)`.
+pub fn canonicalize(s: &str) -> Result<String, DeserializeError> {
+    Ok(serialize_canonical(&deserialize(s)?))
+}
+
+fn serialize_canonical(numbers: &[u16]) -> String {
+    if numbers.is_empty() {
+        return serialize_with_width(numbers, 1).unwrap();
+    }
+    if numbers.iter().all(|n| (1..=300).contains(n)) {
+        return serialize(numbers);
+    }
+    serialize_out_of_range(numbers)
+}
+
+/// Запись чисел, декодированных из режимов с явной шириной или FOR, которые `serialize` не принимает.
+fn serialize_out_of_range(numbers: &[u16]) -> String {
+    let width = bit_width(*numbers.iter().max().unwrap()).max(1);
+    serialize_with_width(numbers, width).unwrap_or_else(|_| {
+        // 16 бит даёт только FOR (минимум до 511 плюс 15-битное смещение) — им и записываем.
+        let mut bitstr = String::new();
+        push_frame_of_reference(&mut bitstr, numbers);
+        bitstring_to_base64(bitstr)
+    })
+}
+
+/// Перекодирует сохранённую запись лучшим режимом (`serialize_best`); если исходная запись
+/// уже не длиннее, она возвращается как есть. Пустой список записывается канонически (`canonicalize`).
+pub fn repack(s: &str) -> Result<String, DeserializeError> {
+    let numbers = deserialize(s)?;
+    let repacked = if numbers.is_empty() {
+        serialize_canonical(&numbers)
+    } else if numbers.iter().all(|n| (1..=300).contains(n)) {
+        serialize_best(&numbers)
+    } else {
+        serialize_out_of_range(&numbers)
+    };
+    Ok(if repacked.len() < s.len() { repacked } else { s.to_string() })
+}
+
+pub fn is_canonical(s: &str) -> bool {
+    canonicalize(s).is_ok_and(|canonical| canonical == s)
+}
+
+pub fn deserialize_fixed(s: &str) -> Result<Vec<u16>, DeserializeError> {
+    deserialize(s.trim_end_matches(FIXED_PADDING))
+}
+
+pub fn deserialize_split(header: &[u8], payload: &[u8]) -> Result<Vec<u16>, DeserializeError> {
+    let header = read_header(&mut BitReader::bytes(header))?;
+    let mut reader = BitReader::bytes(payload);
+    Values::from_header(&mut reader, header, &DeserializeConfig::default())?.collect()
+}
+
+pub fn deserialize_tagged(s: &str) -> Result<Vec<(u16, u8)>, DeserializeError> {
+    let mut reader = BitReader::base64(s);
+    let at_bit = reader.pos;
+    if reader.read_count()? != 0 || reader.read(4)? != MODE_TAGGED {
+        return Err(DeserializeError::InvalidMode { at_bit });
+    }
+    let count = reader.read_count()?;
+    let width = reader.read(4)?;
+    let tag_bits = reader.read(3)? + 1;
+
+    let mut values = Vec::with_capacity(count);
+    for _ in 0..count {
+        let num = reader.read(width)? as u16;
+        let tag = reader.read(tag_bits)? as u8;
+        values.push((num, tag));
+    }
+    Ok(values)
+}
+
+/// Дописывает число в конец записи. Если запись в обычном формате, число помещается в её
+/// ширину, а длина остаётся в том же поле (6 или 10 бит), меняются только первые символы
+/// (длина) и последние (новое число); середина копируется без декодирования и без проверки.
+/// Иначе — при смене ширины или поля длины, в расширенных режимах — запись полностью
+/// декодируется и кодируется заново, как в `canonicalize`. `num` вне 1-300 — ошибка
+/// `ValueOutOfRange`, запись из 1023 чисел — `TooManyElements`.
+pub fn push_serialized(s: &str, num: u16) -> Result<String, DeserializeError> {
+    if !(1..=300).contains(&num) {
+        return Err(DeserializeError::ValueOutOfRange { value: num });
+    }
+    let plain = BitReader::base64(s).read_count()? != 0;
+    let mut reader = BitReader::base64(s);
+    let header = read_header(&mut reader)?;
+    let (count, width, header_bits) = (header.count, header.bits_per_number, reader.pos);
+    let end_bit = header_bits + count * width;
+    let head_chars = header_bits.div_ceil(6);
+    let tail_char = end_bit / 6;
+
+    let in_place = plain
+        && bit_width(num) as usize <= width
+        && (count < 63 || (64..1023).contains(&count))
+        && s.len() == end_bit.div_ceil(6)
+        && head_chars <= tail_char;
+    if !in_place {
+        let mut numbers = deserialize(s)?;
+        if numbers.len() == 1023 {
+            return Err(DeserializeError::TooManyElements { count: 1024, max: 1023 });
+        }
+        numbers.push(num);
+        return Ok(serialize_canonical(&numbers));
+    }
+
+    let mut head = String::new();
+    push_count(&mut head, count + 1);
+    push_bits(&mut head, bits_code(width as u8).unwrap(), 2);
+    push_bits(&mut head, reader.read(head_chars * 6 - header_bits)?, head_chars * 6 - header_bits);
+
+    reader.pos = tail_char * 6;
+    let mut tail = String::new();
+    push_bits(&mut tail, reader.read(end_bit - tail_char * 6)?, end_bit - tail_char * 6);
+    push_bits(&mut tail, num as usize, width);
+
+    Ok(bitstring_to_base64(head) + &s[head_chars..tail_char] + &bitstring_to_base64(tail))
+}
+
+/// Заменяет число с номером `index`. Если новое значение помещается в ширину записи (для FOR —
+/// разность с минимумом), перезаписываются только символы с его битами; иначе, как и для
+/// блочного режима и прогрессий, список декодируется и кодируется заново (`canonicalize`).
+/// Как и в `push_serialized`, `value` вне 1-300 — ошибка `ValueOutOfRange`; на усечённой
+/// записи или чужих символах ошибка та же, что у `deserialize`.
+pub fn set_serialized(s: &str, index: usize, value: u16) -> Result<String, DeserializeError> {
+    if !(1..=300).contains(&value) {
+        return Err(DeserializeError::ValueOutOfRange { value });
+    }
+    let mut reader = BitReader::base64(s);
+    let header = read_header(&mut reader)?;
+    if index >= header.count {
+        return Err(DeserializeError::IndexOutOfBounds { index, len: header.count });
+    }
+    let width = header.bits_per_number;
+    let in_place = header.block_size == 0
+        && header.block_len_bits.is_none()
+        && header.step.is_none()
+        && header.outliers.is_empty()
+        && !header.delta
+        && value >= header.base
+        && bit_width(value - header.base) as usize <= width;
+    let start = reader.pos + index * width;
+    let (first, last) = (start / 6, (start + width).div_ceil(6));
+    // Усечённую запись или чужие символы в неизменной части честно отвергнет полное декодирование.
+    if !in_place || s.len() * 6 < reader.pos + header.count * width || !s.bytes().all(|c| BASE64_CHARS.contains(&c)) {
+        let mut numbers = deserialize(s)?;
+        numbers[index] = value;
+        return Ok(serialize_canonical(&numbers));
+    }
+
+    let mut bits = String::new();
+    reader.pos = first * 6;
+    push_bits(&mut bits, reader.read(start - first * 6)?, start - first * 6);
+    push_bits(&mut bits, (value - header.base) as usize, width);
+    reader.pos = start + width;
+    push_bits(&mut bits, reader.read(last * 6 - start - width)?, last * 6 - start - width);
+
+    Ok(s[..first].to_string() + &bitstring_to_base64(bits) + &s[last..])
+}
+
+pub fn deserialize_i16(s: &str) -> Result<Vec<i16>, DeserializeError> {
+    let mut reader = BitReader::base64(s);
+    let at_bit = reader.pos;
+    if reader.read_count()? != 0 || reader.read(4)? != MODE_ZIGZAG {
+        return Err(DeserializeError::InvalidMode { at_bit });
+    }
+    let count = reader.read_count()?;
+    let width = reader.read(4)?;
+
+    let mut numbers = Vec::with_capacity(count);
+    for _ in 0..count {
+        let zigzag = reader.read(width)? as u16;
+        numbers.push((zigzag >> 1) as i16 ^ -((zigzag & 1) as i16));
+    }
+    Ok(numbers)
+}
+
+pub fn deserialize_counts(s: &str) -> Result<BTreeMap<u16, u32>, DeserializeError> {
+    let mut reader = BitReader::base64(s);
+    let at_bit = reader.pos;
+    if reader.read_count()? != 0 || reader.read(4)? != MODE_COUNTS {
+        return Err(DeserializeError::InvalidMode { at_bit });
+    }
+    let entries = reader.read_count()?;
+    let value_width = reader.read(4)?;
+    let count_width = reader.read(6)?;
+
+    let mut map = BTreeMap::new();
+    for _ in 0..entries {
+        let value = reader.read(value_width)? as u16;
+        let count = reader.read(count_width)? as u32;
+        map.insert(value, count);
+    }
+    Ok(map)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Packing {
+    Mode(Mode),
+    Best(Effort),
+    Width(u8),
+    Blocked(usize),
+}
+
+/// Набор параметров кодирования; свободные функции (`serialize`, `serialize_best`, ...) — это
+/// сокращения для отдельных сочетаний.
+///
+/// Списки для `mode` и `best` проверяются `validate`, а режим `mode` — `Mode::applies_to`,
+/// так что ошибки возвращаются, а не приводят к панике. `width` и `blocked` проверяют вход так же, как `serialize_with_width`
+/// и `serialize_blocked`.
+#[derive(Debug, Clone)]
+pub struct SerializeOptions<'a> {
+    alphabet: &'a Alphabet,
+    packing: Packing,
+    fixed_len: Option<usize>,
+    max_output_len: Option<usize>,
+}
+
+impl Default for SerializeOptions<'_> {
+    fn default() -> Self {
+        SerializeOptions {
+            alphabet: &STANDARD_ALPHABET,
+            packing: Packing::Mode(Mode::Plain),
+            fixed_len: None,
+            max_output_len: None,
+        }
+    }
+}
+
+impl<'a> SerializeOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alphabet(mut self, alphabet: &'a Alphabet) -> Self {
+        self.alphabet = alphabet;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.packing = Packing::Mode(mode);
+        self
+    }
+
+    /// Самый короткий из `Mode::ALL`, как в `serialize_best`.
+    pub fn best(self) -> Self {
+        self.effort(Effort::Max)
+    }
+
+    /// Самый короткий из режимов, которые пробует `effort`, как в `serialize_best_with_effort`.
+    pub fn effort(mut self, effort: Effort) -> Self {
+        self.packing = Packing::Best(effort);
+        self
+    }
+
+    pub fn width(mut self, width: u8) -> Self {
+        self.packing = Packing::Width(width);
+        self
+    }
+
+    pub fn blocked(mut self, block_size: usize) -> Self {
+        self.packing = Packing::Blocked(block_size);
+        self
+    }
+
+    /// Дополняет результат `FIXED_PADDING` до `len` символов, как `serialize_fixed`.
+    pub fn fixed_len(mut self, len: usize) -> Self {
+        self.fixed_len = Some(len);
+        self
+    }
+
+    /// Запись длиннее `max` символов — ошибка `OutputTooLarge`. Длина любой упаковки считается
+    /// до кодирования (`encoded_bits`), так что слишком длинная запись не кодируется вовсе, а из
+    /// кандидатов `best` кодируется только самый короткий. Дополнение `fixed_len` тоже считается.
+    pub fn max_output_len(mut self, max: usize) -> Self {
+        self.max_output_len = Some(max);
+        self
+    }
+
+    fn check_output_len(&self, len: usize) -> Result<(), SerializeError> {
+        match self.max_output_len {
+            Some(max) if len > max => Err(SerializeError::OutputTooLarge { len, max }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn serialize(&self, numbers: &[u16]) -> Result<String, SerializeError> {
+        let bitstr = match self.packing {
+            Packing::Mode(mode) => {
+                validate(numbers)?;
+                if !mode.applies_to(numbers) {
+                    return Err(SerializeError::ModeNotApplicable(mode));
+                }
+                self.check_output_len(encoded_bits(numbers, mode).div_ceil(6))?;
+                to_bitstring_unchecked(numbers, mode)
+            }
+            Packing::Best(effort) => {
+                validate(numbers)?;
+                let (len, mode) = effort
+                    .candidates(numbers)
+                    .into_iter()
+                    // Сравниваются символы, а не биты, чтобы при равной длине выбор совпадал с `serialize_best`.
+                    .map(|mode| (encoded_bits(numbers, mode).div_ceil(6), mode))
+                    .min_by_key(|&(len, _)| len)
+                    .unwrap();
+                self.check_output_len(len)?;
+                to_bitstring_unchecked(numbers, mode)
+            }
+            Packing::Width(width) => {
+                if (1..=15).contains(&width) {
+                    self.check_output_len(plain_len(numbers.len(), width)?)?;
+                }
+                width_bitstring(numbers, width)?
+            }
+            Packing::Blocked(block_size) => {
+                if (1..=64).contains(&block_size) {
+                    validate(numbers)?;
+                    self.check_output_len(blocked_bits(numbers, block_size).div_ceil(6))?;
+                }
+                blocked_bitstring(numbers, block_size)?
+            }
+        };
+        self.check_output_len(bitstr.len().div_ceil(6).max(self.fixed_len.unwrap_or(0)))?;
+        let serialized = bitstring_to_text(bitstr, self.alphabet);
+
+        match self.fixed_len {
+            Some(_) if self.alphabet.lookup[FIXED_PADDING as usize] != NOT_IN_ALPHABET => {
+                Err(SerializeError::PaddingInAlphabet)
+            }
+            Some(len) => pad_fixed(serialized, len),
+            None => Ok(serialized),
+        }
+    }
+}
+
+/// Параметры декодирования, согласованные с `SerializeOptions`.
+#[derive(Debug, Clone)]
+pub struct DeserializeOptions<'a> {
+    alphabet: &'a Alphabet,
+    config: DeserializeConfig,
+    fixed: bool,
+}
+
+impl Default for DeserializeOptions<'_> {
+    fn default() -> Self {
+        DeserializeOptions { alphabet: &STANDARD_ALPHABET, config: DeserializeConfig::default(), fixed: false }
+    }
+}
+
+impl<'a> DeserializeOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alphabet(mut self, alphabet: &'a Alphabet) -> Self {
+        self.alphabet = alphabet;
+        self
+    }
+
+    pub fn max_decode_count(mut self, max: usize) -> Self {
+        self.config.max_decode_count = max;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.strict = strict;
+        self
+    }
+
+    /// Отбрасывает `FIXED_PADDING` в конце строки, как `deserialize_fixed`.
+    pub fn fixed(mut self, fixed: bool) -> Self {
+        self.fixed = fixed;
+        self
+    }
+
+    pub fn deserialize(&self, s: &str) -> Result<Vec<u16>, DeserializeError> {
+        let s = if self.fixed { s.trim_end_matches(FIXED_PADDING) } else { s };
+        decode_with_config(&mut BitReader::text(s, self.alphabet), &self.config)
+    }
+}
+
+/// LRU-кэш результатов `deserialize` по исходной строке.
+#[cfg(feature = "cache")]
+pub struct DecodeCache {
+    capacity: usize,
+    entries: HashMap<String, Rc<Vec<u16>>>,
+    /// Ключи от давно использованных к недавним.
+    order: std::collections::VecDeque<String>,
+}
+
+#[cfg(feature = "cache")]
+impl DecodeCache {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Ёмкость кэша должна быть больше нуля");
+        DecodeCache { capacity, entries: Default::default(), order: Default::default() }
+    }
+
+    pub fn deserialize(&mut self, s: &str) -> Result<Rc<Vec<u16>>, DeserializeError> {
+        if let Some(numbers) = self.entries.get(s) {
+            let numbers = Rc::clone(numbers);
+            let index = self.order.iter().position(|key| key == s).unwrap();
+            let key = self.order.remove(index).unwrap();
+            self.order.push_back(key);
+            return Ok(numbers);
+        }
+
+        let numbers = Rc::new(deserialize(s)?);
+        if self.entries.len() == self.capacity {
+            let oldest = self.order.pop_front().unwrap();
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(s.to_string(), Rc::clone(&numbers));
+        self.order.push_back(s.to_string());
+        Ok(numbers)
+    }
+
+    pub fn contains(&self, s: &str) -> bool {
+        self.entries.contains_key(s)
+    }
+}
+
+/// Байты `serialize_bytes`, сгруппированные по base45: каждые 2 байта дают 3 символа, последний одиночный байт — 2.
+pub fn serialize_base45(numbers: &[u16]) -> String {
+    let mut result = String::new();
+    for pair in serialize_bytes(numbers).chunks(2) {
+        let (mut n, digits) = match *pair {
+            [a, b] => ((a as usize) << 8 | b as usize, 3),
+            [a] => (a as usize, 2),
+            _ => unreachable!(),
+        };
+        for _ in 0..digits {
+            result.push(BASE45_CHARS[n % 45] as char);
+            n /= 45;
+        }
+    }
+    result
+}
+
+pub fn deserialize_base45(s: &str) -> Result<Vec<u16>, DeserializeError> {
+    let digits = s
+        .chars()
+        .enumerate()
+        .map(|(at_char, ch)| {
+            BASE45_CHARS
+                .iter()
+                .position(|&c| c as char == ch)
+                .ok_or(DeserializeError::InvalidChar { ch, at_char })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut bytes = Vec::new();
+    for (i, group) in digits.chunks(3).enumerate() {
+        let n = group.iter().rev().fold(0, |acc, &d| acc * 45 + d);
+        match group.len() {
+            3 if n <= 0xFFFF => bytes.extend_from_slice(&[(n >> 8) as u8, n as u8]),
+            2 if n <= 0xFF => bytes.push(n as u8),
+            _ => return Err(DeserializeError::InvalidGroup { at_char: i * 3 }),
+        }
+    }
+
+    deserialize_bytes(&bytes)
+}
+
+/// Бинарная форма (`serialize_bytes`) для передачи между модулями.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactBytes(pub Vec<u8>);
+
+impl From<&[u16]> for CompactBytes {
+    fn from(numbers: &[u16]) -> Self {
+        CompactBytes(serialize_bytes(numbers))
+    }
+}
+
+impl TryFrom<CompactBytes> for Vec<u16> {
+    type Error = DeserializeError;
+
+    fn try_from(bytes: CompactBytes) -> Result<Self, Self::Error> {
+        deserialize_bytes(&bytes.0)
+    }
+}
+
+pub fn compression_ratio(numbers: &[u16]) -> f64 {
+    let trivial = numbers.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(",");
+    let serialized = serialize(numbers);
+    serialized.len() as f64 / trivial.len() as f64
+}
+
+/// Энтропия Шеннона распределения значений, умноженная на их количество: нижняя граница
+/// числа бит для любого кодера, не учитывающего порядок чисел.
+pub fn entropy_bits(numbers: &[u16]) -> f64 {
+    let mut frequencies: HashMap<u16, usize> = HashMap::new();
+    for &num in numbers {
+        *frequencies.entry(num).or_default() += 1;
+    }
+    let total = numbers.len() as f64;
+    frequencies
+        .values()
+        .map(|&freq| {
+            let p = freq as f64 / total;
+            -(freq as f64) * p.log2()
+        })
+        .sum()
+}
+
+pub struct CompressionStats {
+    pub count: usize,
+    pub trivial_len: usize,
+    pub serialized_len: usize,
+    pub mode: Mode,
+    pub bits_per_value: u8,
+    pub entropy_bits: f64,
+    /// Длина записи в битах до дополнения и её заголовок (`header_bits`).
+    pub bits: usize,
+    pub header_bits: usize,
+}
+
+impl CompressionStats {
+    pub fn ratio(&self) -> f64 {
+        self.serialized_len as f64 / self.trivial_len as f64
+    }
+
+    /// Биты самих чисел в записи, без заголовка и дополнения: для выбросов и позиций — вместе
+    /// с их таблицами, для блоков — с заголовками блоков.
+    pub fn payload_bits(&self) -> usize {
+        self.bits - self.header_bits
+    }
+
+    /// `entropy_bits / payload_bits`; 1.0 — кодер уже на теоретическом минимуме. Больше 1.0
+    /// бывает только у режимов, использующих порядок чисел (блоки, позиции).
+    pub fn efficiency(&self) -> f64 {
+        if self.payload_bits() == 0 {
+            return 1.0;
+        }
+        self.entropy_bits / self.payload_bits() as f64
+    }
+}
+
+/// Режим, который выберет `serialize_best`.
+pub fn recommend_mode(numbers: &[u16]) -> Mode {
+    serialize_best_with_mode(numbers).1
+}
+
+/// Статистика одной записи `serialize_best`; списки, которые `serialize` не примет
+/// (пустые, длиннее 1023 чисел, числа вне 1-300), отклоняются ошибкой `validate`.
+pub fn compression_stats(numbers: &[u16]) -> Result<CompressionStats, SerializeError> {
+    validate(numbers)?;
+    let mode = recommend_mode(numbers);
+    let bits = to_bitstring_unchecked(numbers, mode).len();
+    Ok(CompressionStats {
+        count: numbers.len(),
+        trivial_len: numbers.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(",").len(),
+        serialized_len: bits.div_ceil(6),
+        mode,
+        bits_per_value: bits_per_value(numbers, mode),
+        entropy_bits: entropy_bits(numbers),
+        bits,
+        header_bits: header_bits(numbers, mode),
+    })
+}
+
+/// Разбирает список чисел, разделённых запятыми, точками с запятой или пробельными символами.
+pub fn parse_numbers(text: &str) -> Result<Vec<u16>, String> {
+    parse_tokens(text.split(|c: char| c == ',' || c == ';' || c.is_whitespace()))
+}
+
+/// Разбирает список, разделённый ровно `sep`; пробелы вокруг чисел и пустые элементы
+/// (двойной разделитель, разделитель или перевод строки в конце) пропускаются.
+/// Обратное к `format_numbers`.
+pub fn parse_numbers_with_sep(text: &str, sep: &str) -> Result<Vec<u16>, String> {
+    if sep.is_empty() {
+        return Err("Пустой разделитель".to_string());
+    }
+    parse_tokens(text.split(sep).map(str::trim))
+}
+
+/// Числа через `sep`, как trivial-строка в отчёте, но с любым разделителем.
+pub fn format_numbers(numbers: &[u16], sep: &str) -> String {
+    numbers.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(sep)
+}
+
+fn parse_tokens<'a>(tokens: impl Iterator<Item = &'a str>) -> Result<Vec<u16>, String> {
+    let numbers = tokens
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            token
+                .parse::<u16>()
+                .ok()
+                .filter(|n| (1..=300).contains(n))
+                .ok_or_else(|| format!("Некорректное число {:?}: все числа должны быть в диапазоне 1-300", token))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if numbers.is_empty() {
+        return Err("Входной файл не содержит чисел".to_string());
+    }
+    Ok(numbers)
+}
+
+pub fn read_numbers(path: &str) -> Result<Vec<u16>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Не удалось прочитать {}: {}", path, e))?;
+    parse_numbers(&text)
+}
+
+/// Статистика сжатия одного файла из `analyze_dir`.
+pub struct FileReport {
+    pub path: std::path::PathBuf,
+    pub stats: CompressionStats,
+}
+
+/// Итог `analyze_dir`: файлы с числами и пропущенные файлы с причиной.
+pub struct DirReport {
+    pub files: Vec<FileReport>,
+    pub skipped: Vec<(std::path::PathBuf, String)>,
+}
+
+impl DirReport {
+    pub fn trivial_len(&self) -> usize {
+        self.files.iter().map(|file| file.stats.trivial_len).sum()
+    }
+
+    pub fn serialized_len(&self) -> usize {
+        self.files.iter().map(|file| file.stats.serialized_len).sum()
+    }
+
+    /// Общий коэффициент сжатия; 1.0, если сжимать нечего.
+    pub fn ratio(&self) -> f64 {
+        if self.trivial_len() == 0 {
+            return 1.0;
+        }
+        self.serialized_len() as f64 / self.trivial_len() as f64
+    }
+
+    /// Доля сэкономленного места: `1 - ratio`.
+    pub fn savings(&self) -> f64 {
+        1.0 - self.ratio()
+    }
+}
+
+/// Обходит каталог (с подкаталогами, в порядке имён) и считает `compression_stats` для каждого
+/// `.txt`/`.csv`. Файлы, которые не удалось прочитать или разобрать, и списки, которые `serialize`
+/// не примет (например, длиннее 1023 чисел), попадают в `skipped`. Символические ссылки на
+/// каталоги не обходятся, поэтому цикл из ссылок не приводит к бесконечной рекурсии.
+pub fn analyze_dir(path: impl AsRef<std::path::Path>) -> Result<DirReport, String> {
+    let mut paths = Vec::new();
+    collect_number_files(path.as_ref(), &mut paths)?;
+
+    let mut report = DirReport { files: Vec::new(), skipped: Vec::new() };
+    for path in paths {
+        match read_numbers(&path.to_string_lossy()).and_then(|numbers| compression_stats(&numbers).map_err(|e| e.to_string())) {
+            Ok(stats) => report.files.push(FileReport { stats, path }),
+            Err(reason) => report.skipped.push((path, reason)),
+        }
+    }
+    Ok(report)
+}
+
+fn collect_number_files(dir: &std::path::Path, paths: &mut Vec<std::path::PathBuf>) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("Не удалось прочитать каталог {}: {}", dir.display(), e))?;
+    let mut entries: Vec<(std::path::PathBuf, std::fs::FileType)> = entries
+        .map(|entry| entry.and_then(|entry| Ok((entry.path(), entry.file_type()?))))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Не удалось прочитать каталог {}: {}", dir.display(), e))?;
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (path, file_type) in entries {
+        // `file_type` не переходит по ссылкам, в отличие от `Path::is_dir`.
+        if file_type.is_symlink() && path.is_dir() {
+            continue;
+        }
+        if file_type.is_dir() {
+            collect_number_files(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext == "txt" || ext == "csv") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    const TIER_BOUNDARIES: [(u16, u8); 5] = [(9, 4), (10, 7), (99, 7), (100, 9), (300, 9)];
+
+    fn cases() -> Vec<(&'static str, Vec<u16>)> {
+        let mut rng = rand::rng();
+        vec![
+            ("Простейший короткий 1", vec![1, 2, 3]),
+            ("Простейший короткий 2", vec![1, 2, 3, 4, 5, 6, 7, 8, 9]),
+            ("Случайные 50 чисел", (0..50).map(|_| rng.random_range(1..=300)).collect()),
+            ("Случайные 100 чисел", (0..100).map(|_| rng.random_range(1..=300)).collect()),
+            ("Случайные 500 чисел", (0..500).map(|_| rng.random_range(1..=300)).collect()),
+            ("Случайные 1000 чисел", (0..1000).map(|_| rng.random_range(1..=300)).collect()),
+            ("Граничный: все 1-значные (1..9)", (0..300).map(|i| (i % 9 + 1) as u16).collect()),
+            ("Граничный: все 2-значные (10..99)", (0..300).map(|i| (10 + (i % 90)) as u16).collect()),
+            ("Граничный: все 3-значные (100..300)", (0..300).map(|i| (100 + (i % 201)) as u16).collect()),
+            (
+                "Граничный: каждого числа по 3 раза (от 1 до 300, итого 900 чисел)",
+                (1..=300).flat_map(|n| vec![n; 3]).collect(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        for (desc, data) in cases() {
+            assert_eq!(deserialize(&serialize(&data)), Ok(data), "{}", desc);
+        }
+    }
+
+    #[test]
+    fn round_trip_compact_bytes() {
+        for (desc, data) in cases() {
+            let compact = CompactBytes::from(data.as_slice());
+            assert_eq!(Vec::<u16>::try_from(compact), Ok(data), "{}", desc);
+        }
+    }
+
+    #[test]
+    fn round_trip_base45() {
+        for (desc, data) in cases() {
+            assert_eq!(deserialize_base45(&serialize_base45(&data)), Ok(data), "{}", desc);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "tiers-3-9"))]
+    fn base45_known_grouping() {
+        // [1, 2, 3] упаковывается в байты 0x06 0x09 0x18.
+        assert_eq!(serialize_bytes(&[1, 2, 3]), vec![0x06, 0x09, 0x18]);
+        assert_eq!(serialize_base45(&[1, 2, 3]), "FY0O0");
+        assert_eq!(deserialize_base45("GGW"), Err(DeserializeError::InvalidGroup { at_char: 0 }));
+    }
+
+    #[test]
+    fn round_trip_best() {
+        for (desc, data) in cases() {
+            let best = serialize_best(&data);
+            assert!(best.len() <= serialize(&data).len(), "{}", desc);
+            assert_eq!(deserialize(&best), Ok(data), "{}", desc);
+        }
+    }
+
+    #[test]
+    fn serialize_rejects_bad_length() {
+        let long = vec![1; 2000];
+        assert!(std::panic::catch_unwind(|| serialize(&long)).is_err());
+        assert!(std::panic::catch_unwind(|| serialize_best(&long)).is_err());
+        assert!(std::panic::catch_unwind(|| serialize_best_with_effort(&long, Effort::Fast)).is_err());
+        assert!(std::panic::catch_unwind(|| serialize_best_with_mode(&[])).is_err());
+        assert_eq!(deserialize(&serialize_best(&long[..1023])), Ok(long[..1023].to_vec()));
+    }
+
+    #[test]
+    fn frame_of_reference_beats_plain_on_three_digits() {
+        let data: Vec<u16> = (0..300).map(|i| (100 + (i % 201)) as u16).collect();
+        let encoded = serialize_with_mode(&data, Mode::FrameOfReference);
+        assert!(encoded.len() < serialize(&data).len());
+        assert_eq!(serialize_best_with_effort(&data, Effort::Balanced), encoded);
+        assert_eq!(deserialize(&encoded), Ok(data));
+    }
+
+    #[test]
+    fn frame_of_reference_constant_input() {
+        let data = vec![250; 40];
+        assert_eq!(bits_per_value(&data, Mode::FrameOfReference), 0);
+        assert_eq!(deserialize(&serialize_with_mode(&data, Mode::FrameOfReference)), Ok(data));
+    }
+
+    #[test]
+    #[cfg(not(feature = "tiers-3-9"))]
+    fn tier_boundaries_select_expected_width() {
+        for (value, width) in TIER_BOUNDARIES {
+            assert_eq!(determine_bits_per_number(&[value]), width, "value {}", value);
+        }
+    }
+
+    #[test]
+    fn tier_boundaries_round_trip() {
+        for (value, _) in TIER_BOUNDARIES {
+            assert_eq!(deserialize(&serialize(&[value])), Ok(vec![value]), "value {}", value);
+        }
+    }
+
+    #[test]
+    fn custom_tier_table() {
+        let narrow: &[(u16, u8)] = &[(8, 3), (512, 9)];
+        assert_eq!(determine_bits_per_number_with(&[1, 5, 7], narrow), 3);
+        assert_eq!(determine_bits_per_number_with(&[8], narrow), 9);
+    }
+
+    #[test]
+    fn truncated_compact_bytes() {
+        let truncated = Vec::<u16>::try_from(CompactBytes(vec![0b1000_0000]));
+        assert_eq!(truncated, Err(DeserializeError::UnexpectedEof { needed: 10, available: 7 }));
+    }
+
+    #[test]
+    fn corrupted_input_reports_offset() {
+        assert_eq!(deserialize("Ae"), Err(DeserializeError::InvalidMode { at_bit: 7 }));
+        assert_eq!(deserialize("Ag?A"), Err(DeserializeError::InvalidChar { ch: '?', at_char: 2 }));
+        assert_eq!(deserialize("ЖAAA"), Err(DeserializeError::InvalidChar { ch: 'Ж', at_char: 0 }));
+    }
+
+    #[test]
+    fn three_concatenated_records() {
+        let batches: Vec<Vec<u16>> = vec![vec![1, 2, 3], (1..=100).collect(), vec![300; 70]];
+        let concatenated: String = batches.iter().map(|b| serialize(b)).collect();
+        assert_eq!(deserialize_prefix(&concatenated), Ok((vec![1, 2, 3], serialize(&[1, 2, 3]).len())));
+        assert_eq!(decode_all_concatenated(&concatenated), Ok(batches));
+    }
+
+    #[test]
+    #[cfg(not(feature = "tiers-3-9"))]
+    fn padding_at_six_bit_boundary() {
+        // 9 бит заголовка + числа: [10, 20, 30] и [300] ровно кратны 6, [10, 20] и [1, 2] на бит меньше.
+        let cases: Vec<(Vec<u16>, usize)> =
+            vec![(vec![10, 20, 30], 30), (vec![300], 18), (vec![10, 20], 23), (vec![1, 2], 17)];
+        for (data, bits) in &cases {
+            let serialized = serialize(data);
+            assert_eq!(serialized.len(), bits.div_ceil(6), "{:?}", data);
+            assert_eq!(deserialize_prefix(&serialized), Ok((data.clone(), serialized.len())));
+        }
+        let concatenated: String = cases.iter().map(|(data, _)| serialize(data)).collect();
+        let expected: Vec<Vec<u16>> = cases.into_iter().map(|(data, _)| data).collect();
+        assert_eq!(decode_all_concatenated(&concatenated), Ok(expected));
+    }
+
+    #[test]
+    fn equivalent_inputs_canonicalize_identically() {
+        let canonical = serialize(&[1, 2, 3]);
+        // 21 бит в 4 символах: три младших бита последнего символа — дополнение.
+        let mut with_padding = canonical.clone().into_bytes();
+        let last = with_padding.last_mut().unwrap();
+        *last = BASE64_CHARS[BASE64_CHARS.iter().position(|c| c == last).unwrap() | 0b101];
+        let with_padding = String::from_utf8(with_padding).unwrap();
+        let frame_of_reference = serialize_with_mode(&[1, 2, 3], Mode::FrameOfReference);
+
+        for input in [&canonical, &with_padding, &frame_of_reference] {
+            assert_eq!(canonicalize(input).as_ref(), Ok(&canonical), "{}", input);
+        }
+        assert!(is_canonical(&canonical));
+        assert!(!is_canonical(&with_padding));
+        assert!(!is_canonical(&frame_of_reference));
+        assert!(!is_canonical("Ae"));
+
+        let wide = serialize_with_width(&[2000, 1], 12).unwrap();
+        assert_eq!(canonicalize(&wide), Ok(serialize_with_width(&[2000, 1], 11).unwrap()));
+
+        let empty = serialize_with_width(&[], 1).unwrap();
+        let empties = [
+            serialize_with_width(&[], 4).unwrap(),
+            intersect_serialized(&[&serialize(&[1]), &serialize(&[2])]).unwrap(),
+            serialize_sorted_deltas(std::iter::empty()).unwrap(),
+        ];
+        for input in &empties {
+            assert_eq!(canonicalize(input).as_ref(), Ok(&empty), "{}", input);
+        }
+        assert!(is_canonical(&empty));
+    }
+
+    #[test]
+    fn blocked_sizes_round_trip() {
+        // Медленно растущая последовательность: узкие блоки выгоднее одного общего FOR
+        // (блоки переменной длины из `Effort::Max` здесь не сравниваются).
+        let ramp: Vec<u16> = (0..600).map(|i| (i / 2 + 1) as u16).collect();
+        for block_size in [8, 16, 64] {
+            let blocked = serialize_blocked(&ramp, block_size).unwrap();
+            assert_eq!(deserialize(&blocked), Ok(ramp.clone()), "block size {}", block_size);
+            assert!(blocked.len() < serialize_best_with_effort(&ramp, Effort::Balanced).len(), "block size {}", block_size);
+        }
+        for (desc, data) in cases() {
+            let blocked = serialize_blocked(&data, DEFAULT_BLOCK_SIZE).unwrap();
+            assert_eq!(deserialize(&blocked), Ok(data), "{}", desc);
+        }
+        assert_eq!(serialize_blocked(&ramp, 65), Err(SerializeError::InvalidBlockSize(65)));
+        assert_eq!(serialize_blocked(&ramp, 0), Err(SerializeError::InvalidBlockSize(0)));
+    }
+
+    #[test]
+    fn entropy_of_uniform_and_constant_lists() {
+        assert_eq!(entropy_bits(&[]), 0.0);
+        assert_eq!(entropy_bits(&[7; 10]), 0.0);
+        let uniform: Vec<u16> = (1..=16).cycle().take(64).collect();
+        assert!((entropy_bits(&uniform) - 256.0).abs() < 1e-9);
+
+        let stats = compression_stats(&uniform).unwrap();
+        assert_eq!(stats.payload_bits(), 64 * stats.bits_per_value as usize);
+        assert!(stats.efficiency() > 0.0 && stats.efficiency() <= 1.0);
+        assert_eq!(compression_stats(&[5; 10]).unwrap().efficiency(), 1.0);
+
+        // Позиции: 7 + 4 + 11 бит длин со знаком режима, 7 бит числа групп и 9 бит заполнителя.
+        let stats = compression_stats(&scattered_pairs()).unwrap();
+        assert_eq!(stats.mode, Mode::Positions);
+        assert_eq!(stats.header_bits, 38);
+        assert_eq!(stats.bits.div_ceil(6), stats.serialized_len);
+        assert_eq!(stats.payload_bits(), stats.bits - 38);
+        // Позиции используют порядок чисел, поэтому обходят энтропийную границу.
+        assert!(stats.efficiency() > 1.0);
+
+        let long = vec![7; 1500];
+        assert_eq!(compression_stats(&long).err(), Some(SerializeError::Invalid(vec![Violation::TooManyNumbers(1500)])));
+        assert!(compression_stats(&[]).is_err());
+    }
+
+    #[test]
+    fn header_bits_match_decoder() {
+        let mut spiky: Vec<u16> = (0..300).map(|i| i % 7 + 1).collect();
+        spiky[100] = 300;
+        let extra = [(1..=300).collect(), spiky, phase_change(), scattered_pairs()];
+        for data in cases().into_iter().map(|(_, data)| data).chain(extra) {
+            for mode in Mode::ALL.into_iter().filter(|mode| mode.applies_to(&data)) {
+                let bits = to_bitstring_unchecked(&data, mode);
+                let serialized = bitstring_to_base64(bits.clone());
+                let mut reader = BitReader::base64(&serialized);
+                let header = read_header(&mut reader).unwrap();
+                // Таблицы выбросов и позиций `read_header` читает вместе с заголовком.
+                let expected = match mode {
+                    Mode::Outliers => header_bits(&data, mode) + header.outliers.len() * 19,
+                    Mode::Positions => bits.len(),
+                    _ => header_bits(&data, mode),
+                };
+                assert_eq!(reader.pos, expected, "{}", mode.name());
+                assert_eq!(encoded_bits(&data, mode), bits.len(), "{}", mode.name());
+            }
+            for block_size in [1, 7, DEFAULT_BLOCK_SIZE, 64] {
+                assert_eq!(blocked_bits(&data, block_size), blocked_bitstring(&data, block_size).unwrap().len());
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_length_output() {
+        let exact_len = serialize(&[1, 2, 3]).len();
+        let short = serialize_fixed(&[1, 2, 3], exact_len + 4).unwrap();
+        assert_eq!(short, format!("{}====", serialize(&[1, 2, 3])));
+        assert_eq!(deserialize_fixed(&short), Ok(vec![1, 2, 3]));
+
+        let exact = serialize_fixed(&[1, 2, 3], exact_len).unwrap();
+        assert_eq!(exact, serialize(&[1, 2, 3]));
+        assert_eq!(deserialize_fixed(&exact), Ok(vec![1, 2, 3]));
+
+        assert_eq!(
+            serialize_fixed(&[1, 2, 3], exact_len - 1),
+            Err(SerializeError::OutputTooLong { len: exact_len, max: exact_len - 1 })
+        );
+    }
+
+    #[test]
+    fn stats_count_modes_across_calls() {
+        let mut stats = Stats::new();
+        assert_eq!(stats.mode_share(Mode::Plain), 0.0);
+
+        let narrow: Vec<u16> = (0..20).map(|i| 290 + i % 4).collect();
+        let inputs: [&[u16]; 4] = [&[1, 2, 3], &narrow, &[1, 2, 3], &[4, 5, 6]];
+        let mut bytes_out = 0;
+        for numbers in inputs {
+            let serialized = serialize_with_stats(numbers, &mut stats);
+            assert_eq!(serialized, serialize_best(numbers));
+            bytes_out += serialized.len();
+        }
+
+        assert_eq!(stats.calls, 4);
+        assert_eq!(stats.bytes_in, 2 * 29);
+        assert_eq!(stats.bytes_out, bytes_out);
+        assert_eq!(stats.mode_count(Mode::Plain), 3);
+        assert_eq!(stats.mode_count(Mode::FrameOfReference), 1);
+        assert_eq!(stats.mode_share(Mode::FrameOfReference), 0.25);
+        assert!(stats.report().contains("frame_of_reference 25.0%"));
+    }
+
+    #[test]
+    fn word_reads_match_bit_by_bit() {
+        for width in 1..=15u8 {
+            let values: Vec<u16> = (0..200u32).map(|i| (i * 40503 % (1 << width)) as u16).collect();
+            let serialized = serialize_with_width(&values, width).unwrap();
+            assert_eq!(deserialize(&serialized), Ok(values.clone()), "width {}", width);
+
+            let mut reader = BitReader::base64(&serialized);
+            let header = read_header(&mut reader).unwrap();
+            let bit_by_bit: Vec<u16> =
+                (0..header.count).map(|_| reader.read(header.bits_per_number).unwrap() as u16).collect();
+            assert_eq!(bit_by_bit, values, "width {}", width);
+        }
+
+        // Ошибки внутри слова сообщаются так же, как при побитовом чтении.
+        let serialized = serialize(&(1..=300).collect::<Vec<u16>>());
+        let mut corrupted = serialized.clone().into_bytes();
+        corrupted[100] = b'?';
+        let corrupted = String::from_utf8(corrupted).unwrap();
+        assert_eq!(deserialize(&corrupted), Err(DeserializeError::InvalidChar { ch: '?', at_char: 100 }));
+        assert_eq!(deserialize_take(&corrupted, 10), Ok((1..=10).collect()));
+        let truncated = &serialized[..serialized.len() - 3];
+        assert!(matches!(deserialize(truncated), Err(DeserializeError::UnexpectedEof { needed: 9, .. })));
+    }
+
+    #[test]
+    fn deserialize_into_wider_types() {
+        let serialized = serialize(&[1, 150, 300]);
+        assert_eq!(deserialize_as::<u32>(&serialized), Ok(vec![1u32, 150, 300]));
+        assert_eq!(deserialize_as::<usize>(&serialized), Ok(vec![1usize, 150, 300]));
+        assert_eq!(deserialize_as::<u32>("Ae"), Err(DeserializeError::InvalidMode { at_bit: 7 }));
+    }
+
+    #[test]
+    fn strict_mode_rejects_trailing_data() {
+        let strict = DeserializeConfig { strict: true, ..Default::default() };
+        for (desc, data) in cases() {
+            let serialized = serialize(&data);
+            assert_eq!(deserialize_with_config(&serialized, &strict), Ok(data), "{}", desc);
+        }
+
+        // Длина 2, ширина 4, но записаны три числа.
+        let mut bitstr = String::new();
+        push_count(&mut bitstr, 2);
+        push_bits(&mut bitstr, 0b00, 2);
+        for num in [1, 2, 3] {
+            push_bits(&mut bitstr, num, 4);
+        }
+        let short_count = bitstring_to_base64(bitstr);
+        assert_eq!(deserialize(&short_count), Ok(vec![1, 2]));
+        assert_eq!(deserialize_with_config(&short_count, &strict), Err(DeserializeError::TrailingData { at_bit: 17 }));
+
+        let extra_char = format!("{}A", serialize(&[1, 2, 3]));
+        assert!(matches!(deserialize_with_config(&extra_char, &strict), Err(DeserializeError::TrailingData { .. })));
+    }
+
+    #[test]
+    fn repack_shrinks_over_wide_encoding() {
+        let small: Vec<u16> = (0..100).map(|i| i * 37 % 100 + 1).collect();
+        let over_wide = serialize_with_width(&small, 12).unwrap();
+        let repacked = repack(&over_wide).unwrap();
+        assert!(repacked.len() < over_wide.len());
+        assert_eq!(repacked, serialize_best(&small));
+        assert_eq!(deserialize(&repacked), Ok(small));
+
+        let wide = serialize_with_width(&[2000, 1], 15).unwrap();
+        assert_eq!(deserialize(&repack(&wide).unwrap()), Ok(vec![2000, 1]));
+
+        let ramp: Vec<u16> = (0..600).map(|i| (i / 2 + 1) as u16).collect();
+        let blocked = serialize_blocked(&ramp, 8).unwrap();
+        assert_eq!(repack(&blocked), Ok(blocked.clone()));
+
+        let empty = intersect_serialized(&[&serialize(&[1]), &serialize(&[2])]).unwrap();
+        assert_eq!(repack(&empty), Ok(canonicalize(&empty).unwrap()));
+        assert_eq!(deserialize(&repack(&serialize_with_width(&[], 12).unwrap()).unwrap()), Ok(vec![]));
+    }
+
+    #[test]
+    fn options_defaults_match_free_functions() {
+        for (desc, data) in cases() {
+            assert_eq!(SerializeOptions::new().serialize(&data), Ok(serialize(&data)), "{}", desc);
+            assert_eq!(SerializeOptions::new().best().serialize(&data), Ok(serialize_best(&data)), "{}", desc);
+            assert_eq!(DeserializeOptions::new().deserialize(&serialize(&data)), Ok(data), "{}", desc);
+        }
+        assert_eq!(SerializeOptions::new().width(12).serialize(&[2000, 1]), serialize_with_width(&[2000, 1], 12));
+        assert_eq!(SerializeOptions::new().blocked(8).serialize(&[5, 6, 7]), serialize_blocked(&[5, 6, 7], 8));
+    }
+
+    #[test]
+    fn options_combinations_round_trip() {
+        let url_safe = Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_").unwrap();
+        let sample: Vec<u16> = (250..=300).collect();
+
+        let encoder = SerializeOptions::new().alphabet(&url_safe).mode(Mode::FrameOfReference).fixed_len(64);
+        let decoder = DeserializeOptions::new().alphabet(&url_safe).fixed(true).strict(true);
+        let encoded = encoder.serialize(&sample).unwrap();
+        assert_eq!(encoded.len(), 64);
+        assert!(encoded.ends_with(FIXED_PADDING));
+        assert_eq!(decoder.deserialize(&encoded), Ok(sample.clone()));
+        assert!(DeserializeOptions::new().alphabet(&url_safe).deserialize(&encoded).is_ok());
+
+        let blocked = SerializeOptions::new().alphabet(&url_safe).blocked(16).serialize(&sample).unwrap();
+        assert_eq!(decoder.deserialize(&blocked), Ok(sample.clone()));
+        assert_eq!(
+            DeserializeOptions::new().max_decode_count(10).deserialize(&serialize(&sample)),
+            Err(DeserializeError::TooManyElements { count: 51, max: 10 })
+        );
+    }
+
+    #[test]
+    fn options_report_errors() {
+        assert_eq!(
+            SerializeOptions::new().mode(Mode::FrameOfReference).serialize(&[1, 301]),
+            Err(SerializeError::Invalid(vec![Violation::OutOfRange { index: 1, value: 301 }]))
+        );
+        assert_eq!(
+            SerializeOptions::new().mode(Mode::Arithmetic).serialize(&[1, 2, 4]),
+            Err(SerializeError::ModeNotApplicable(Mode::Arithmetic))
+        );
+        assert_eq!(
+            SerializeOptions::new().mode(Mode::Arithmetic).serialize(&[3, 5, 7]),
+            Ok(serialize_with_mode(&[3, 5, 7], Mode::Arithmetic))
+        );
+        assert_eq!(
+            SerializeOptions::new().fixed_len(2).serialize(&[1, 2, 3]),
+            Err(SerializeError::OutputTooLong { len: serialize(&[1, 2, 3]).len(), max: 2 })
+        );
+        let with_padding = Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+=").unwrap();
+        assert_eq!(
+            SerializeOptions::new().alphabet(&with_padding).fixed_len(10).serialize(&[1]),
+            Err(SerializeError::PaddingInAlphabet)
+        );
+    }
+
+    #[test]
+    fn take_first_five_of_thousand() {
+        let large: Vec<u16> = (0..1000).map(|i| (i % 300 + 1) as u16).collect();
+        assert_eq!(deserialize_take(&serialize(&large), 5), Ok(large[..5].to_vec()));
+        assert_eq!(deserialize_take("Ae", 5), Err(DeserializeError::InvalidMode { at_bit: 7 }));
+    }
+
+    #[test]
+    fn explicit_width() {
+        let digits: Vec<u16> = (1..=9).collect();
+        let forced = serialize_with_width(&digits, 6).unwrap();
+        assert_eq!(deserialize(&forced), Ok(digits));
+        assert_eq!(
+            serialize_with_width(&[1, 100], 6),
+            Err(SerializeError::ValueTooWide { index: 1, value: 100, width: 6 })
+        );
+        assert_eq!(serialize_with_width(&[1], 0), Err(SerializeError::InvalidWidth(0)));
+    }
+
+    #[test]
+    fn twelve_bit_tier() {
+        assert_eq!(determine_bits_per_number(&[511]), 9);
+        assert_eq!(determine_bits_per_number(&[512]), 12);
+        assert_eq!(determine_bits_per_number(&[2000]), 12);
+
+        let wide = serialize_with_width(&[2000, 1, 4095], 12).unwrap();
+        // 0 000011 11: короткая длина 3 и код 11 без расширенного заголовка.
+        assert!(wide.starts_with("B7"));
+        assert_eq!(deserialize(&wide), Ok(vec![2000, 1, 4095]));
+    }
+
+    #[test]
+    fn explicit_width_of_empty_list() {
+        let empty = serialize_with_width(&[], 4).unwrap();
+        assert_eq!(deserialize(&empty), Ok(vec![]));
+    }
+
+    #[test]
+    fn validate_reports_every_violation() {
+        assert_eq!(validate(&[1, 150, 300]), Ok(()));
+        assert_eq!(
+            validate(&[0, 5, 301, 7, 1000]),
+            Err(SerializeError::Invalid(vec![
+                Violation::OutOfRange { index: 0, value: 0 },
+                Violation::OutOfRange { index: 2, value: 301 },
+                Violation::OutOfRange { index: 4, value: 1000 },
+            ]))
+        );
+        assert_eq!(validate(&[]), Err(SerializeError::Invalid(vec![Violation::Empty])));
+
+        let mut too_many = vec![1; 1024];
+        too_many[1023] = 0;
+        assert_eq!(
+            validate(&too_many),
+            Err(SerializeError::Invalid(vec![
+                Violation::TooManyNumbers(1024),
+                Violation::OutOfRange { index: 1023, value: 0 },
+            ]))
+        );
+    }
+
+    #[test]
+    fn estimated_len_matches_serialize() {
+        for (desc, data) in cases() {
+            assert_eq!(estimated_len(&data), Ok(serialize(&data).len()), "{}", desc);
+        }
+    }
+
+    #[test]
+    fn size_overflow_instead_of_wrapping() {
+        assert_eq!(plain_len(1023, 9), Ok(serialize(&[300; 1023]).len()));
+        assert_eq!(plain_len(usize::MAX / 4, 9), Err(SerializeError::SizeOverflow { count: usize::MAX / 4, width: 9 }));
+        // Сами биты чисел помещаются, переполняет добавленный заголовок.
+        assert_eq!(plain_len(usize::MAX / 9, 9), Err(SerializeError::SizeOverflow { count: usize::MAX / 9, width: 9 }));
+        assert_eq!(plain_len(usize::MAX / 15, 15), Err(SerializeError::SizeOverflow { count: usize::MAX / 15, width: 15 }));
+    }
+
+    #[test]
+    fn serialize_into_slice() {
+        let data: Vec<u16> = (1..=300).collect();
+        let mut out = vec![b'.'; estimated_len(&data).unwrap() + 2];
+        let written = serialize_into(&data, &mut out).unwrap();
+        assert_eq!(&out[..written], serialize(&data).as_bytes());
+        assert_eq!(&out[written..], b"..");
+
+        assert_eq!(estimated_len(&[]), Err(SerializeError::Invalid(vec![Violation::Empty])));
+        assert_eq!(serialize_into(&[], &mut out), Err(SerializeError::Invalid(vec![Violation::Empty])));
+        assert_eq!(
+            estimated_len(&[5000]),
+            Err(SerializeError::Invalid(vec![Violation::OutOfRange { index: 0, value: 5000 }]))
+        );
+        assert_eq!(
+            serialize_into(&[0], &mut out),
+            Err(SerializeError::Invalid(vec![Violation::OutOfRange { index: 0, value: 0 }]))
+        );
+        let long = vec![1; 2000];
+        assert_eq!(serialize_into(&long, &mut out), Err(SerializeError::Invalid(vec![Violation::TooManyNumbers(2000)])));
+    }
+
+    #[test]
+    #[cfg(all(feature = "mmap", unix))]
+    fn serialize_to_mapped_file() {
+        let data: Vec<u16> = (0..1000).map(|i| (i % 300 + 1) as u16).collect();
+        let path = std::env::temp_dir().join(format!("serialize2-mmap-{}.txt", std::process::id()));
+        let written = serialize_to_mmap(&data, &path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, contents.len());
+        assert_eq!(deserialize(&contents), Ok(data));
+
+        for invalid in [vec![0], vec![1; 2000]] {
+            let err = serialize_to_mmap(&invalid, &path).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+            assert!(!path.exists());
+        }
+    }
+
+    #[test]
+    fn data_uri_round_trip() {
+        for (desc, data) in cases() {
+            let uri = serialize_data_uri(&data);
+            assert!(uri.starts_with("data:application/octet-stream;base64,"), "{}", desc);
+            assert_eq!((uri.len() - DATA_URI_PREFIX.len()) % 4, 0, "{}", desc);
+            assert_eq!(deserialize_data_uri(&uri).as_ref(), Ok(&data), "{}", desc);
+        }
+
+        // Среди этих длин есть записи с одиночным символом в последней группе base64.
+        for len in 1..=12 {
+            let data: Vec<u16> = (1..=len).collect();
+            let uri = serialize_data_uri(&data);
+            assert!(!uri.ends_with("==="), "{}", uri);
+            assert_eq!(deserialize_data_uri(&uri), Ok(data));
+        }
+
+        let payload = serialize(&[1, 2, 3]);
+        assert_eq!(deserialize_data_uri(&format!("data:text/plain;base64,{}", payload)), Err(DeserializeError::InvalidDataUri));
+        assert_eq!(deserialize_data_uri(&payload), Err(DeserializeError::InvalidDataUri));
+    }
+
+    #[test]
+    fn peek_count_reads_only_length() {
+        let short: Vec<u16> = (1..=63).collect();
+        let long: Vec<u16> = (0..500).map(|i| i % 300 + 1).collect();
+        assert_eq!(peek_count(&serialize(&short)), Ok(63));
+        assert_eq!(peek_count(&serialize(&[7; 64])), Ok(64));
+        assert_eq!(peek_count(&serialize(&long)), Ok(500));
+        assert_eq!(peek_count(&serialize_best(&long)), Ok(500));
+        assert_eq!(peek_count(&serialize_with_mode(&short, Mode::FrameOfReference)), Ok(63));
+        assert_eq!(peek_count(&serialize_with_width(&long, 12).unwrap()), Ok(500));
+        // Заголовок без чисел: длина 1023, но данных нет.
+        assert_eq!(peek_count("//"), Ok(1023));
+        assert_eq!(peek_count("Ae"), Err(DeserializeError::InvalidMode { at_bit: 7 }));
+        assert_eq!(peek_count(""), Err(DeserializeError::UnexpectedEof { needed: 1, available: 0 }));
+    }
+
+    #[test]
+    fn best_reports_its_mode() {
+        let mut spiky: Vec<u16> = (0..300).map(|i| i % 7 + 1).collect();
+        spiky[100] = 300;
+        let extra = [(1..=300).collect(), (0..100).map(|i| 200 + i % 4).collect(), spiky, phase_change(), scattered_pairs()];
+        let mut seen = BTreeSet::new();
+        for data in cases().into_iter().map(|(_, data)| data).chain(extra) {
+            let (serialized, mode) = serialize_best_with_mode(&data);
+            assert_eq!(serialized, serialize_best(&data));
+            assert_eq!(inspect(&serialized), Ok(Some(mode)));
+            seen.insert(mode.name());
+        }
+        assert_eq!(seen.len(), Mode::ALL.len());
+
+        assert_eq!(inspect(&serialize_blocked(&[1, 2, 3], 2).unwrap()), Ok(None));
+        assert_eq!(inspect(&serialize_with_width(&[1, 2, 3], 12).unwrap()), Ok(Some(Mode::Plain)));
+        assert_eq!(inspect("Ae"), Err(DeserializeError::InvalidMode { at_bit: 7 }));
+    }
+
+    #[test]
+    fn unchecked_matches_serialize() {
+        for (desc, data) in cases() {
+            assert_eq!(serialize_unchecked(&data), serialize(&data), "{}", desc);
+        }
+    }
+
+    // Зафиксированные записи: при изменении формата эти тесты должны падать, а не подстраиваться.
+    #[test]
+    #[cfg(not(feature = "tiers-3-9"))]
+    fn golden_plain_outputs() {
+        assert_eq!(serialize(&[1, 2, 3]), "BgkY");
+        assert_eq!(serialize(&[1, 2, 3, 4, 5, 6, 7, 8, 9]), "EgkaKzxI");
+        assert_eq!(serialize(&[10, 99, 100, 300]), "CQKMZklg");
+        assert_eq!(
+            serialize(&(1..=70).collect::<Vec<u16>>()),
+            "iMgQQMIFDBxAkULGDRw8gRJEyhUsXMGTRs4dPH0CFEjSJUydQpVK1i1cvYMWTNo1bN3Dl07ePXz+BBhQ4kWM"
+        );
+        assert_eq!(serialize_base45(&[1, 2, 3]), "FY0O0");
+    }
+
+    #[test]
+    #[cfg(feature = "tiers-3-9")]
+    fn golden_plain_outputs() {
+        assert_eq!(serialize(&[1, 2, 3]), "ACDMpg");
+        assert_eq!(serialize(&[1, 2, 3, 4, 5, 6, 7, 8, 9]), "EwBAQDAgFAwHBAJ");
+        assert_eq!(serialize(&[10, 99, 100, 300]), "CQKMZklg");
+        assert_eq!(serialize_base45(&[1, 2, 3]), "W00A*P");
+    }
+
+    #[test]
+    fn golden_extended_outputs() {
+        assert_eq!(serialize_with_mode(&[290, 291, 295], Mode::FrameOfReference), "AADkRg0");
+        assert_eq!(serialize_with_mode(&[5, 8, 11, 14], Mode::Arithmetic), "AKEAoBg");
+        assert_eq!(serialize_with_mode(&[1, 2, 300, 3], Mode::Outliers), "AOEICAUsYw");
+        assert_eq!(serialize_with_width(&[1, 2, 3], 12).as_deref(), Ok("B4AIAQAY"));
+        assert_eq!(serialize_blocked(&[1, 2, 3, 200, 201, 202], 3).as_deref(), Ok("AGGCAJDMghg"));
+        assert_eq!(serialize_sorted_deltas([3, 3, 10, 11].into_iter()).as_deref(), Ok("AQEAZjk"));
+        assert_eq!(serialize_i16(&[-1, 0, 1]).as_deref(), Ok("AMDJI"));
+    }
+
+    #[test]
+    fn sorted_deltas() {
+        let sorted = [3, 3, 10, 11, 200, 200, 300];
+        let encoded = serialize_sorted_deltas(sorted.iter().copied()).unwrap();
+        assert_eq!(deserialize(&encoded), Ok(sorted.to_vec()));
+
+        let dense: Vec<u16> = (0..900).map(|i| 1 + i / 3).collect();
+        let encoded = serialize_sorted_deltas(dense.iter().copied()).unwrap();
+        assert_eq!(deserialize(&encoded), Ok(dense.clone()));
+        assert!(encoded.len() * 4 < serialize(&dense).len());
+
+        assert_eq!(serialize_sorted_deltas([5, 7, 6].into_iter()), Err(SerializeError::NotSorted { index: 2, prev: 7, value: 6 }));
+        assert_eq!(
+            serialize_sorted_deltas([5, 0].into_iter()),
+            Err(SerializeError::Invalid(vec![Violation::OutOfRange { index: 1, value: 0 }]))
+        );
+        assert_eq!(serialize_sorted_deltas(std::iter::repeat_n(1, 1024)), Err(SerializeError::TooManyNumbers(1024)));
+        assert_eq!(deserialize(&serialize_sorted_deltas(std::iter::empty()).unwrap()), Ok(vec![]));
+        assert_eq!(deserialize(&serialize_sorted_deltas(std::iter::once(42)).unwrap()), Ok(vec![42]));
+    }
+
+    #[test]
+    fn outliers_win_on_single_digits_with_spikes() {
+        let mut data: Vec<u16> = (0..300).map(|i| i % 3 + 1).collect();
+        data[100] = 300;
+        data[200] = 150;
+
+        let best = serialize_best(&data);
+        assert_eq!(recommend_mode(&data), Mode::Outliers);
+        assert_eq!(bits_per_value(&data, Mode::Outliers), 2);
+        assert_eq!(deserialize(&best), Ok(data.clone()));
+        assert!(best.len() * 4 < serialize(&data).len(), "{} vs {}", best.len(), serialize(&data).len());
+        assert!(best.len() < serialize_blocked(&data, DEFAULT_BLOCK_SIZE).unwrap().len());
+
+        for (desc, data) in cases() {
+            assert_eq!(deserialize(&serialize_with_mode(&data, Mode::Outliers)).as_ref(), Ok(&data), "{}", desc);
+        }
+        let all_outliers = serialize_with_mode(&[300, 299], Mode::Outliers);
+        assert_eq!(deserialize(&all_outliers), Ok(vec![300, 299]));
+    }
+
+    #[test]
+    fn outlier_list_is_checked() {
+        let header = |outliers: &[(usize, usize)]| {
+            let mut bits = String::new();
+            push_count(&mut bits, 0);
+            push_bits(&mut bits, MODE_OUTLIERS, 4);
+            push_count(&mut bits, 3);
+            push_bits(&mut bits, 2, 4);
+            push_count(&mut bits, outliers.len());
+            for &(index, num) in outliers {
+                push_bits(&mut bits, index, 10);
+                push_bits(&mut bits, num, 9);
+            }
+            push_bits(&mut bits, 0b01_00_11, 6);
+            bitstring_to_base64(bits)
+        };
+        assert_eq!(deserialize(&header(&[(1, 300)])), Ok(vec![1, 300, 3]));
+        assert_eq!(deserialize(&header(&[(3, 300)])), Err(DeserializeError::IndexOutOfBounds { index: 3, len: 3 }));
+        assert_eq!(deserialize(&header(&[(1, 300), (1, 299)])), Err(DeserializeError::InvalidMode { at_bit: 48 }));
+        let too_many = header(&[(0, 9), (1, 9), (2, 9), (2, 9)]);
+        assert_eq!(deserialize(&too_many), Err(DeserializeError::TooManyElements { count: 4, max: 3 }));
+    }
+
+    #[test]
+    fn effort_limits_candidates() {
+        for (desc, data) in cases() {
+            let fast = serialize_best_with_effort(&data, Effort::Fast);
+            let balanced = serialize_best_with_effort(&data, Effort::Balanced);
+            let max = serialize_best_with_effort(&data, Effort::Max);
+            assert_eq!(deserialize(&fast).as_ref(), Ok(&data), "{}", desc);
+            assert_eq!(deserialize(&balanced).as_ref(), Ok(&data), "{}", desc);
+            assert!(max.len() <= balanced.len() && balanced.len() <= fast.len(), "{}", desc);
+            assert_eq!(max, serialize_best(&data), "{}", desc);
+            assert_eq!(Effort::Fast.candidates(&data).len(), 1, "{}", desc);
+            assert_eq!(SerializeOptions::new().effort(Effort::Fast).serialize(&data), Ok(fast), "{}", desc);
+        }
+
+        let ramp: Vec<u16> = (1..=300).collect();
+        assert_eq!(Effort::Fast.candidates(&ramp), [Mode::Arithmetic]);
+        let narrow: Vec<u16> = (0..100).map(|i| 200 + i % 4).collect();
+        assert_eq!(Effort::Fast.candidates(&narrow), [Mode::FrameOfReference]);
+        assert_eq!(Effort::Balanced.candidates(&narrow), [Mode::Plain, Mode::FrameOfReference]);
+    }
+
+    #[test]
+    fn bytes_str_matches_deserialize() {
+        let numbers: Vec<u16> = (1..=300).collect();
+        let serialized = serialize(&numbers);
+        assert_eq!(deserialize_bytes_str(serialized.as_bytes()), Ok(numbers));
+
+        let mut bytes = serialize(&[1, 2, 3]).into_bytes();
+        bytes[1] = 0xFF;
+        assert_eq!(deserialize_bytes_str(&bytes), Err(DeserializeError::InvalidChar { ch: '\u{FF}', at_char: 1 }));
+    }
+
+    #[test]
+    fn stream_frames_decode_concatenated() {
+        let numbers: Vec<u16> = (0..2500).map(|i| (i * 7 % 300 + 1) as u16).collect();
+        let mut out = Vec::new();
+        let stats = serialize_stream(numbers.iter().copied(), &mut out).unwrap();
+
+        let frames = decode_all_concatenated(std::str::from_utf8(&out).unwrap()).unwrap();
+        assert_eq!(frames.iter().map(Vec::len).collect::<Vec<_>>(), [1023, 1023, 454]);
+        assert_eq!(frames.concat(), numbers);
+        let trivial = numbers.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(",");
+        assert_eq!(stats, StreamStats { count: 2500, frames: 3, trivial_len: trivial.len(), serialized_len: out.len() });
+
+        assert_eq!(serialize_stream(std::iter::empty(), std::io::sink()).unwrap(), StreamStats::default());
+        let error = serialize_stream([1, 301], std::io::sink()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn chunked_frames_decode_concatenated() {
+        let numbers: Vec<u16> = (0..1000).map(|i| (i * 13 % 300 + 1) as u16).collect();
+        let frames: Vec<String> = chunked_serialize(numbers.iter().copied(), 256).collect();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(deserialize(&frames[3]).map(|frame| frame.len()), Ok(1000 - 3 * 256));
+
+        let decoded = decode_all_concatenated(&frames.concat()).unwrap();
+        assert_eq!(decoded.iter().map(Vec::len).collect::<Vec<_>>(), [256, 256, 256, 232]);
+        assert_eq!(decoded.concat(), numbers);
+        assert_eq!(chunked_serialize(std::iter::empty(), 256).next(), None);
+    }
+
+    #[test]
+    fn framed_round_trip() {
+        let numbers: Vec<u16> = (0..5000).map(|i| (i * 7 % 300 + 1) as u16).collect();
+        let frames = serialize_framed(&numbers, 1000).unwrap();
+        assert_eq!(frames.len(), 5);
+        for frame in &frames {
+            assert_eq!(peek_count(frame), Ok(1000));
+        }
+        assert_eq!(deserialize_framed(&frames), Ok(numbers.clone()));
+
+        let frames = serialize_framed(&numbers, 1023).unwrap();
+        assert_eq!(frames.len(), 5);
+        assert_eq!(peek_count(&frames[4]), Ok(5000 - 4 * 1023));
+        assert_eq!(deserialize_framed(&frames), Ok(numbers));
+
+        assert_eq!(serialize_framed(&[], 1000), Ok(vec![]));
+        assert_eq!(serialize_framed(&[1, 2], 0), Err(SerializeError::InvalidFrameSize(0)));
+        assert_eq!(serialize_framed(&[1, 2], 1024), Err(SerializeError::InvalidFrameSize(1024)));
+        assert_eq!(
+            serialize_framed(&[1, 301, 0], 2),
+            Err(SerializeError::Invalid(vec![
+                Violation::OutOfRange { index: 1, value: 301 },
+                Violation::OutOfRange { index: 2, value: 0 },
+            ]))
+        );
+        assert_eq!(deserialize_framed(&[]), Ok(vec![]));
+        let broken = [serialize(&[1, 2]), "A".to_string()];
+        assert!(matches!(deserialize_framed(&broken), Err(DeserializeError::UnexpectedEof { .. })));
+    }
+
+    #[test]
+    fn analyze_directory() {
+        let dir = std::env::temp_dir().join(format!("serialize2-analyze-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("a.txt"), "1, 2, 3").unwrap();
+        std::fs::write(dir.join("nested").join("b.csv"), "100;200;300\n250").unwrap();
+        std::fs::write(dir.join("broken.txt"), "1, 999").unwrap();
+        std::fs::write(dir.join("notes.md"), "не числа").unwrap();
+        std::fs::write(dir.join("long.txt"), format_numbers(&[7; 1500], ",")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("nested").join("loop")).unwrap();
+
+        let report = analyze_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = report.files.iter().map(|file| file.path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, ["a.txt", "b.csv"]);
+        let skipped: Vec<_> = report.skipped.iter().map(|(path, _)| path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(skipped, ["broken.txt", "long.txt"]);
+        assert!(report.skipped[1].1.contains("1500 numbers"));
+        assert_eq!(report.trivial_len(), "1,2,3".len() + "100,200,300,250".len());
+        assert_eq!(report.serialized_len(), serialize_best(&[1, 2, 3]).len() + serialize_best(&[100, 200, 300, 250]).len());
+        assert!((report.savings() - (1.0 - report.ratio())).abs() < 1e-12);
+        assert!(analyze_dir(dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn tagged_round_trip() {
+        let values: Vec<(u16, u8)> = (1..=200).map(|i| (i, (i % 4) as u8)).collect();
+        let encoded = serialize_tagged(&values, 2).unwrap();
+        assert_eq!(deserialize_tagged(&encoded), Ok(values));
+        assert!(deserialize(&encoded).is_err());
+        assert!(deserialize_tagged(&serialize(&[1, 2, 3])).is_err());
+    }
+
+    #[test]
+    fn tagged_rejects_wide_tags() {
+        assert_eq!(
+            serialize_tagged(&[(5, 1), (6, 4)], 2),
+            Err(SerializeError::TagTooWide { index: 1, tag: 4, tag_bits: 2 })
+        );
+        assert_eq!(serialize_tagged(&[(5, 1)], 0), Err(SerializeError::InvalidTagWidth(0)));
+    }
+
+    #[test]
+    fn counts_round_trip() {
+        let single = BTreeMap::from([(42, 1_000_000)]);
+        let encoded = serialize_counts(&single);
+        assert!(encoded.len() <= 10, "{}", encoded);
+        assert_eq!(deserialize_counts(&encoded), Ok(single));
+
+        let map = BTreeMap::from([(1, 3), (7, 0), (150, 42), (300, u32::MAX)]);
+        assert_eq!(deserialize_counts(&serialize_counts(&map)), Ok(map));
+        assert_eq!(deserialize_counts(&serialize_counts(&BTreeMap::new())), Ok(BTreeMap::new()));
+        assert!(deserialize_counts(&serialize(&[1, 2, 3])).is_err());
+        assert!(deserialize(&serialize_counts(&BTreeMap::from([(1, 2)]))).is_err());
+    }
+
+    #[test]
+    fn arithmetic_sequences() {
+        let rising: Vec<u16> = (0..99).map(|i| 5 + 3 * i).collect();
+        assert_eq!(&rising[..4], &[5, 8, 11, 14]);
+        let encoded = serialize_best(&rising);
+        assert_eq!(recommend_mode(&rising), Mode::Arithmetic);
+        assert!(encoded.len() <= 7, "{}", encoded);
+        assert_eq!(deserialize(&encoded), Ok(rising));
+
+        // В диапазоне 1-300 прогрессия из 1000 чисел бывает только постоянной.
+        let long = vec![300; 1000];
+        assert!(serialize_best(&long).len() <= 7);
+        assert_eq!(deserialize(&serialize_best(&long)), Ok(long));
+
+        let falling: Vec<u16> = (1..=300).rev().collect();
+        assert_eq!(deserialize(&serialize_with_mode(&falling, Mode::Arithmetic)), Ok(falling));
+
+        assert!(!Mode::Arithmetic.applies_to(&[1, 2, 4]));
+        assert!(!Mode::Arithmetic.applies_to(&[]));
+        assert_ne!(recommend_mode(&[1, 2, 4, 8, 16, 32, 64, 128]), Mode::Arithmetic);
+    }
+
+    #[test]
+    fn push_within_and_across_tiers() {
+        let mut numbers: Vec<u16> = (1..=9).collect();
+        let mut serialized = serialize(&numbers);
+        // 10..63 чисел: ширина 4 бита и короткая длина сохраняются, дальше длина становится 10-битной.
+        for i in 0..100 {
+            let num = i % 9 + 1;
+            serialized = push_serialized(&serialized, num).unwrap();
+            numbers.push(num);
+            assert_eq!(serialized, serialize(&numbers), "after {} numbers", numbers.len());
+        }
+
+        // Число шире текущих 4 бит — полное перекодирование.
+        serialized = push_serialized(&serialized, 250).unwrap();
+        numbers.push(250);
+        assert_eq!(serialized, serialize(&numbers));
+        assert_eq!(deserialize(&push_serialized(&serialized, 300).unwrap()), Ok([numbers, vec![300]].concat()));
+
+        let frame_of_reference = serialize_with_mode(&[290, 291], Mode::FrameOfReference);
+        assert_eq!(push_serialized(&frame_of_reference, 292), Ok(serialize(&[290, 291, 292])));
+        assert!(push_serialized("Ae", 1).is_err());
+
+        // Запись этой же библиотеки с числом вне 1-300 перекодируется без паники.
+        let wide = serialize_with_width(&[2000], 11).unwrap();
+        assert_eq!(deserialize(&push_serialized(&wide, 5).unwrap()), Ok(vec![2000, 5]));
+        assert_eq!(push_serialized(&serialized, 0), Err(DeserializeError::ValueOutOfRange { value: 0 }));
+        assert_eq!(push_serialized(&serialized, 301), Err(DeserializeError::ValueOutOfRange { value: 301 }));
+        assert_eq!(
+            push_serialized(&serialize(&[7; 1023]), 7),
+            Err(DeserializeError::TooManyElements { count: 1024, max: 1023 })
+        );
+    }
+
+    #[test]
+    fn set_in_place_and_with_fallback() {
+        let numbers: Vec<u16> = (0..50).map(|i| i * 2 + 10).collect();
+        let serialized = serialize(&numbers);
+        let mut expected = numbers.clone();
+
+        expected[20] = 99;
+        let in_place = set_serialized(&serialized, 20, 99).unwrap();
+        assert_eq!(in_place, serialize(&expected));
+        let changed = in_place.bytes().zip(serialized.bytes()).filter(|(a, b)| a != b).count();
+        assert!(changed <= 2, "{} chars changed", changed);
+
+        expected[49] = 250;
+        let wider = set_serialized(&in_place, 49, 250).unwrap();
+        assert_eq!(wider, serialize(&expected));
+        assert_eq!(deserialize(&wider), Ok(expected));
+
+        let frame_of_reference = serialize_with_mode(&[290, 291, 295], Mode::FrameOfReference);
+        let updated = set_serialized(&frame_of_reference, 0, 293).unwrap();
+        assert_eq!(updated.len(), frame_of_reference.len());
+        assert_eq!(deserialize(&updated), Ok(vec![293, 291, 295]));
+        assert_eq!(deserialize(&set_serialized(&frame_of_reference, 0, 1).unwrap()), Ok(vec![1, 291, 295]));
+
+        assert_eq!(
+            set_serialized(&serialized, 50, 1),
+            Err(DeserializeError::IndexOutOfBounds { index: 50, len: 50 })
+        );
+        // 400 и 0 помещаются в 9 бит записи, но `serialize` их не примет.
+        let plain = serialize(&[300, 1]);
+        assert_eq!(set_serialized(&plain, 1, 400), Err(DeserializeError::ValueOutOfRange { value: 400 }));
+        assert_eq!(set_serialized(&plain, 1, 0), Err(DeserializeError::ValueOutOfRange { value: 0 }));
+    }
+
+    #[test]
+    fn set_rejects_truncated_and_non_ascii() {
+        for truncated in ["bz", "GU89Y"] {
+            let err = deserialize(truncated).unwrap_err();
+            assert!(matches!(err, DeserializeError::UnexpectedEof { .. }), "{}", truncated);
+            assert_eq!(set_serialized(truncated, 0, 5), Err(err.clone()), "{}", truncated);
+            assert_eq!(set_serialized(truncated, 2, 300), Err(err), "{}", truncated);
+        }
+
+        let numbers: Vec<u16> = (0..20).map(|i| i * 15 + 1).collect();
+        let mut non_ascii = serialize(&numbers);
+        non_ascii.replace_range(2..4, "é");
+        let err = deserialize(&non_ascii).unwrap_err();
+        for index in [0, 10, 19] {
+            assert_eq!(set_serialized(&non_ascii, index, 7), Err(err.clone()), "{}", index);
+        }
+    }
+
+    #[test]
+    fn very_short_inputs_are_rejected() {
+        assert_eq!(deserialize(""), Err(DeserializeError::UnexpectedEof { needed: 1, available: 0 }));
+        assert_eq!(deserialize("A"), Err(DeserializeError::UnexpectedEof { needed: 6, available: 5 }));
+        assert_eq!(deserialize("g"), Err(DeserializeError::UnexpectedEof { needed: 10, available: 5 }));
+        assert_eq!(deserialize_bytes(&[0x80]), Err(DeserializeError::UnexpectedEof { needed: 10, available: 7 }));
+        // Флаг длинной длины, но длина 0 и 1.
+        assert_eq!(deserialize("gA"), Err(DeserializeError::InvalidCount { count: 0, at_bit: 0 }));
+        assert_eq!(deserialize("gC"), Err(DeserializeError::InvalidCount { count: 1, at_bit: 0 }));
+
+        let chars: Vec<char> = BASE64_CHARS.iter().map(|&c| c as char).collect();
+        for &a in &chars {
+            assert!(deserialize(&a.to_string()).is_err());
+            for &b in &chars {
+                let _ = deserialize(&format!("{}{}", a, b));
+                let _ = deserialize_prefix(&format!("{}{}", a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn protobuf_packed_matches_known_bytes() {
+        // Пример из документации protobuf и значения на границах 7 и 14 бит.
+        assert_eq!(serialize_protobuf_packed(&[3, 270]), vec![0x03, 0x8e, 0x02]);
+        assert_eq!(serialize_protobuf_packed(&[1, 150, 300]), vec![0x01, 0x96, 0x01, 0xac, 0x02]);
+        assert_eq!(serialize_protobuf_packed(&[127, 128, 65535]), vec![0x7f, 0x80, 0x01, 0xff, 0xff, 0x03]);
+        assert_eq!(deserialize_protobuf_packed(&[0x03, 0x8e, 0x02]), Ok(vec![3, 270]));
+
+        for (desc, data) in cases() {
+            assert_eq!(deserialize_protobuf_packed(&serialize_protobuf_packed(&data)), Ok(data), "{}", desc);
+        }
+        assert_eq!(deserialize_protobuf_packed(&[0x01, 0x96]), Err(DeserializeError::InvalidVarint { at_byte: 1 }));
+        assert_eq!(deserialize_protobuf_packed(&[0x80, 0x80, 0x04]), Err(DeserializeError::InvalidVarint { at_byte: 0 }));
+        assert_eq!(deserialize_protobuf_packed(&[0x80, 0x80, 0x80, 0x01]), Err(DeserializeError::InvalidVarint { at_byte: 0 }));
+    }
+
+    #[test]
+    fn raw_transcoders_round_trip() {
+        for (desc, data) in cases() {
+            let raw: Vec<u8> = data.iter().flat_map(|num| num.to_le_bytes()).collect();
+            let mut encoded = Vec::new();
+            assert_eq!(transcode_from_raw(raw.as_slice(), &mut encoded).unwrap(), data.len(), "{}", desc);
+            assert_eq!(encoded, serialize(&data).into_bytes(), "{}", desc);
+
+            let mut decoded = Vec::new();
+            assert_eq!(transcode_to_raw(encoded.as_slice(), &mut decoded).unwrap(), data.len(), "{}", desc);
+            assert_eq!(decoded, raw, "{}", desc);
+        }
+
+        let error = transcode_from_raw(&[1, 0, 2][..], Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        let error = transcode_from_raw(&[0x2d, 0x01][..], Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        let error = transcode_to_raw(&b"Ae"[..], Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    /// Отдаёт `data`, а затем ошибку вместо конца потока.
+    struct FailAfter<'a>(&'a [u8]);
+
+    impl std::io::Read for FailAfter<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(std::io::Error::other("обрыв соединения"));
+            }
+            let len = buf.len().min(self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn transcode_to_raw_streams_values() {
+        let data: Vec<u16> = (0..1000).map(|i| (i % 300 + 1) as u16).collect();
+        let serialized = serialize(&data);
+        let raw: Vec<u8> = data.iter().flat_map(|num| num.to_le_bytes()).collect();
+
+        // Числа из первой половины записаны до того, как вход оборвался.
+        let mut decoded = Vec::new();
+        let half = &serialized.as_bytes()[..serialized.len() / 2];
+        assert!(transcode_to_raw(FailAfter(half), &mut decoded).is_err());
+        assert!(decoded.len() > 400 * 2);
+        assert!(raw.starts_with(&decoded));
+
+        // После записи вход больше не читается, хвост с ошибкой не мешает.
+        let mut decoded = Vec::new();
+        assert_eq!(transcode_to_raw(FailAfter(serialized.as_bytes()), &mut decoded).unwrap(), 1000);
+        assert_eq!(decoded, raw);
+        let mut with_newline = serialized.clone().into_bytes();
+        with_newline.push(b'\n');
+        assert_eq!(transcode_to_raw(with_newline.as_slice(), std::io::sink()).unwrap(), 1000);
+
+        let error = transcode_to_raw(half, std::io::sink()).unwrap_err();
+        let inner = error.into_inner().unwrap().downcast::<DeserializeError>().unwrap();
+        assert!(matches!(*inner, DeserializeError::UnexpectedEof { .. }));
+    }
+
+    #[test]
+    fn output_stays_in_alphabet() {
+        let url_safe = Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_").unwrap();
+        let mut rng = rand::rng();
+        for _ in 0..300 {
+            let len = rng.random_range(1..=1023);
+            let low = rng.random_range(1..=300);
+            let high = rng.random_range(low..=300);
+            let data: Vec<u16> = (0..len).map(|_| rng.random_range(low..=high)).collect();
+
+            assert!(serialize(&data).bytes().all(|c| BASE64_CHARS.contains(&c)), "{:?}", data);
+            assert!(serialize_with_alphabet(&data, &url_safe).bytes().all(|c| url_safe.chars.contains(&c)), "{:?}", data);
+            assert!(serialize_best(&data).bytes().all(|c| BASE64_CHARS.contains(&c)), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn deserialize_from_stops_at_the_limit() {
+        let data: Vec<u16> = (1..=300).collect();
+        let serialized = serialize(&data);
+        assert_eq!(deserialize_from(serialized.as_bytes(), 1024), Ok(data.clone()));
+        let with_tail = format!("{}остаток", serialized);
+        assert_eq!(deserialize_from(with_tail.as_bytes(), serialized.len()), Ok(data));
+
+        // Заголовок обещает 1000 чисел, дальше поток никогда не кончается.
+        let header = serialize(&vec![300; 1000])[..3].to_string();
+        let endless = std::io::Read::chain(header.as_bytes(), std::io::repeat(b'A'));
+        assert_eq!(deserialize_from(endless, 100), Err(DeserializeError::LimitExceeded { max_bytes: 100 }));
+
+        assert!(matches!(deserialize_from(&serialized.as_bytes()[..10], 1024), Err(DeserializeError::UnexpectedEof { .. })));
+        assert_eq!(deserialize_from(&b"Ag?A"[..], 1024), Err(DeserializeError::InvalidChar { ch: '?', at_char: 2 }));
+    }
+
+    #[test]
+    fn signed_round_trip() {
+        let signed = [-5, 0, 300, -300];
+        let encoded = serialize_i16(&signed).unwrap();
+        assert_eq!(deserialize_i16(&encoded), Ok(signed.to_vec()));
+        assert!(deserialize(&encoded).is_err());
+
+        let all: Vec<i16> = (-300..=300).collect();
+        assert_eq!(deserialize_i16(&serialize_i16(&all).unwrap()), Ok(all));
+        assert_eq!(deserialize_i16(&serialize_i16(&[0, 0]).unwrap()), Ok(vec![0, 0]));
+        assert_eq!(serialize_i16(&[1, -301]), Err(SerializeError::SignedOutOfRange { index: 1, value: -301 }));
+        assert!(deserialize_i16(&serialize(&[1, 2, 3])).is_err());
+    }
+
+    #[test]
+    fn split_round_trip() {
+        for (desc, data) in cases() {
+            let (header, payload) = serialize_split(&data);
+            assert_eq!(payload.len(), (data.len() * determine_bits_per_number(&data) as usize).div_ceil(8));
+            assert_eq!(deserialize_split(&header, &payload), Ok(data), "{}", desc);
+        }
+    }
+
+    #[test]
+    fn split_with_truncated_payload() {
+        let (header, payload) = serialize_split(&[100, 200, 300]);
+        assert_eq!(
+            deserialize_split(&header, &payload[..2]),
+            Err(DeserializeError::UnexpectedEof { needed: 9, available: 7 })
+        );
+    }
+
+    #[test]
+    fn custom_alphabet() {
+        let url_safe = Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_").unwrap();
+        let sample: Vec<u16> = (250..=300).collect();
+        let custom = serialize_with_alphabet(&sample, &url_safe);
+        assert_eq!(deserialize_with_alphabet(&custom, &url_safe), Ok(sample));
+    }
+
+    #[test]
+    fn alphabet_with_duplicate_is_rejected() {
+        let duplicated = Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+A");
+        assert_eq!(duplicated.unwrap_err(), AlphabetError::Duplicate { ch: 'A', first: 0, second: 63 });
+        assert_eq!(Alphabet::new("ABC").unwrap_err(), AlphabetError::WrongLength(3));
+    }
+
+    #[test]
+    fn tail_is_returned_intact() {
+        let framed = format!("{};остаток", serialize(&[1, 2, 3]));
+        assert_eq!(deserialize_with_tail(&framed), Ok((vec![1, 2, 3], ";остаток")));
+    }
+
+    #[test]
+    #[cfg(feature = "cache")]
+    fn decode_cache_hits_and_evicts() {
+        let mut cache = DecodeCache::new(2);
+        let (a, b, c) = (serialize(&[1, 2, 3]), serialize(&[10, 20]), serialize(&[300]));
+        let first = cache.deserialize(&a).unwrap();
+        let hit = cache.deserialize(&a).unwrap();
+        assert!(Rc::ptr_eq(&first, &hit));
+        assert_eq!(*hit, [1, 2, 3]);
+
+        cache.deserialize(&b).unwrap();
+        cache.deserialize(&a).unwrap();
+        cache.deserialize(&c).unwrap();
+        assert!(!cache.contains(&b));
+        assert!(cache.contains(&a) && cache.contains(&c));
+    }
+
+    #[test]
+    fn declared_count_above_limit_is_rejected() {
+        let limited = DeserializeConfig { max_decode_count: 100, ..Default::default() };
+        let declared_500 = serialize(&vec![7; 500]);
+        let expected = Err(DeserializeError::TooManyElements { count: 500, max: 100 });
+        assert_eq!(deserialize_with_config(&declared_500, &limited), expected);
+        // Хватает заголовка: числа даже не читаются.
+        assert_eq!(deserialize_with_config(&declared_500[..5], &limited), expected);
+    }
+
+    #[test]
+    fn union_and_intersection_of_shards() {
+        let shard_a = serialize(&(1..=200).collect::<Vec<u16>>());
+        let shard_b = serialize(&(150..=300).step_by(2).collect::<Vec<u16>>());
+        let shard_c = serialize(&[300, 299, 150, 150]);
+        let expected_union: Vec<u16> =
+            (1..=200).chain((202..=300).step_by(2)).chain([299]).collect::<BTreeSet<_>>().into_iter().collect();
+
+        let union = union_serialized(&[&shard_a, &shard_b, &shard_c]).unwrap();
+        assert_eq!(deserialize(&union), Ok(expected_union));
+        let intersection = intersect_serialized(&[&shard_a, &shard_b, &shard_c]).unwrap();
+        assert_eq!(deserialize(&intersection), Ok(vec![150]));
+        let disjoint = intersect_serialized(&[&serialize(&[1, 2]), &serialize(&[3])]).unwrap();
+        assert_eq!(deserialize(&disjoint), Ok(vec![]));
+
+        // Записи с явной шириной могут хранить числа вне 1-300.
+        let wide = serialize_with_width(&[2000], 11).unwrap();
+        let max_9_bit = serialize_with_width(&[511, 5], 9).unwrap();
+        let zero = serialize_with_width(&[0, 5], 3).unwrap();
+        let union = union_serialized(&[&wide, &max_9_bit, &zero]).unwrap();
+        assert_eq!(deserialize(&union), Ok(vec![0, 5, 511, 2000]));
+        let intersection = intersect_serialized(&[&max_9_bit, &zero]).unwrap();
+        assert_eq!(deserialize(&intersection), Ok(vec![5]));
+        assert_eq!(deserialize(&intersect_serialized(&[&wide]).unwrap()), Ok(vec![2000]));
+
+        let low = serialize_with_width(&(1000..1700).collect::<Vec<_>>(), 11).unwrap();
+        let high = serialize_with_width(&(1600..2100).collect::<Vec<_>>(), 12).unwrap();
+        assert_eq!(union_serialized(&[&low, &high]), Err(DeserializeError::TooManyElements { count: 1100, max: 1023 }));
+    }
+
+    fn phase_change() -> Vec<u16> {
+        let low = (0..200).map(|i| 1 + i % 4);
+        let high = (0..150).map(|i| 250 + i % 8);
+        let mid = (0..100).map(|i| 100 + i % 2);
+        low.chain(high).chain(mid).collect()
+    }
+
+    #[test]
+    fn deserialize_into_reuses_buffer() {
+        let mut buf = Vec::with_capacity(1023);
+        let ptr = buf.as_ptr();
+        for (desc, data) in cases() {
+            buf.clear();
+            assert_eq!(deserialize_into(&serialize_best(&data), &mut buf), Ok(data.len()), "{}", desc);
+            assert_eq!(buf, data, "{}", desc);
+            assert_eq!(buf.as_ptr(), ptr, "{}", desc);
+        }
+
+        buf.clear();
+        assert_eq!(deserialize_into(&serialize(&[1, 2]), &mut buf), Ok(2));
+        assert_eq!(deserialize_into(&serialize(&[3]), &mut buf), Ok(1));
+        assert_eq!(buf, [1, 2, 3]);
+        let truncated = &serialize(&(1..=300).collect::<Vec<_>>())[..100];
+        assert!(matches!(deserialize_into(truncated, &mut buf), Err(DeserializeError::UnexpectedEof { .. })));
+        assert_eq!(buf, [1, 2, 3]);
+    }
+
+    fn scattered_pairs() -> Vec<u16> {
+        [1, 1, 2, 1, 2, 2, 1].repeat(100)
+    }
+
+    #[test]
+    fn positions_win_on_scattered_repeats() {
+        // Сами 7 чисел `[1, 1, 2, 1, 2, 2, 1]` короче заголовка режима, поэтому берётся повтор.
+        let data = scattered_pairs();
+        let positions = serialize_with_mode(&data, Mode::Positions);
+        assert_eq!(deserialize(&positions), Ok(data.clone()));
+        for mode in Mode::ALL.into_iter().filter(|&mode| mode != Mode::Positions && mode.applies_to(&data)) {
+            assert!(positions.len() < serialize_with_mode(&data, mode).len(), "{}", mode.name());
+        }
+        assert_eq!(serialize_best_with_mode(&data), (positions, Mode::Positions));
+
+        let wide: Vec<u16> = (0..300).map(|i| if i % 17 == 0 { 299 } else { 250 }).collect();
+        assert_eq!(serialize_best_with_mode(&wide).1, Mode::Positions);
+        assert_eq!(bits_per_value(&wide, Mode::Positions), 5);
+
+        for (desc, data) in cases() {
+            assert_eq!(deserialize(&serialize_with_mode(&data, Mode::Positions)).as_ref(), Ok(&data), "{}", desc);
+        }
+        assert_eq!(deserialize(&serialize_with_mode(&[9, 9, 9], Mode::Positions)), Ok(vec![9, 9, 9]));
+    }
+
+    #[test]
+    fn positions_reject_overlaps() {
+        let record = |groups: &[(usize, &[usize])]| {
+            let mut bits = String::new();
+            push_count(&mut bits, 0);
+            push_bits(&mut bits, MODE_POSITIONS, 4);
+            push_count(&mut bits, 4);
+            push_count(&mut bits, groups.len());
+            push_bits(&mut bits, 1, 9);
+            for &(value, gaps) in groups {
+                push_bits(&mut bits, value, 9);
+                push_bits(&mut bits, gaps.len(), 3);
+                push_bits(&mut bits, 2, 4);
+                for &gap in gaps {
+                    push_bits(&mut bits, gap, 2);
+                }
+            }
+            bitstring_to_base64(bits)
+        };
+        assert_eq!(deserialize(&record(&[(5, &[1]), (7, &[0, 1])])), Ok(vec![7, 5, 7, 1]));
+        assert_eq!(deserialize(&record(&[(5, &[1]), (7, &[1])])), Err(DeserializeError::InvalidMode { at_bit: 7 }));
+        assert_eq!(deserialize(&record(&[(5, &[3, 0])])), Err(DeserializeError::IndexOutOfBounds { index: 4, len: 4 }));
+    }
+
+    #[test]
+    fn variable_blocks_beat_fixed_blocks() {
+        let data = phase_change();
+        assert_eq!(optimal_block_boundaries(&data), [200, 350, 450]);
+
+        let variable = serialize_with_mode(&data, Mode::VariableBlocks);
+        assert_eq!(deserialize(&variable), Ok(data.clone()));
+        let best_fixed = (1..=64).map(|size| serialize_blocked(&data, size).unwrap().len()).min().unwrap();
+        assert!(variable.len() < best_fixed, "{} vs {}", variable.len(), best_fixed);
+        assert_eq!(serialize_best_with_mode(&data).1, Mode::VariableBlocks);
+        assert!(!Effort::Balanced.candidates(&data).contains(&Mode::VariableBlocks));
+
+        for (desc, data) in cases() {
+            assert_eq!(deserialize(&serialize_with_mode(&data, Mode::VariableBlocks)).as_ref(), Ok(&data), "{}", desc);
+        }
+        assert_eq!(deserialize(&serialize_with_mode(&[7], Mode::VariableBlocks)), Ok(vec![7]));
+    }
+
+    #[test]
+    fn block_boundaries_match_brute_force() {
+        let mut rng = rand::rng();
+        for _ in 0..50 {
+            let data: Vec<u16> = (0..rng.random_range(1..=10)).map(|_| rng.random_range(1..=300)).collect();
+            let header_bits = block_len_bits(data.len()) + 13;
+            let cost = |ends: &[usize]| {
+                let starts = std::iter::once(0).chain(ends.iter().copied());
+                starts
+                    .zip(ends)
+                    .map(|(start, &end)| {
+                        let block = &data[start..end];
+                        header_bits + block.len() * bit_width(block.iter().max().unwrap() - block.iter().min().unwrap()) as usize
+                    })
+                    .sum::<usize>()
+            };
+            // Каждый бит маски — конец блока после соответствующего числа.
+            let brute = (0..1u32 << (data.len() - 1))
+                .map(|mask| {
+                    let mut ends: Vec<usize> = (1..data.len()).filter(|&i| mask >> (i - 1) & 1 == 1).collect();
+                    ends.push(data.len());
+                    cost(&ends)
+                })
+                .min()
+                .unwrap();
+            assert_eq!(cost(&optimal_block_boundaries(&data)), brute, "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn auto_encoding_targets_decode() {
+        for (desc, data) in cases() {
+            let text = serialize_auto_encoding(&data, Target::Text);
+            let binary = serialize_auto_encoding(&data, Target::Binary);
+            assert_eq!(text, Encoded::Text(serialize(&data)), "{}", desc);
+            assert_eq!(binary, Encoded::Binary(serialize_bytes(&data)), "{}", desc);
+            assert_eq!(text.decode().as_ref(), Ok(&data), "{}", desc);
+            assert_eq!(binary.decode().as_ref(), Ok(&data), "{}", desc);
+        }
+    }
+
+    #[test]
+    fn max_output_len_rejects_oversized_output() {
+        let data: Vec<u16> = (0..500).map(|i| i % 300 + 1).collect();
+        let len = serialize(&data).len();
+        assert_eq!(SerializeOptions::new().max_output_len(len).serialize(&data), Ok(serialize(&data)));
+        assert_eq!(
+            SerializeOptions::new().max_output_len(len - 1).serialize(&data),
+            Err(SerializeError::OutputTooLarge { len, max: len - 1 })
+        );
+
+        let best = serialize_best(&data);
+        assert_eq!(
+            SerializeOptions::new().best().max_output_len(10).serialize(&data),
+            Err(SerializeError::OutputTooLarge { len: best.len(), max: 10 })
+        );
+        let wide = SerializeOptions::new().width(12).max_output_len(100).serialize(&data);
+        assert_eq!(wide, Err(SerializeError::OutputTooLarge { len: serialize_with_width(&data, 12).unwrap().len(), max: 100 }));
+        assert_eq!(
+            SerializeOptions::new().fixed_len(64).max_output_len(40).serialize(&[1, 2, 3]),
+            Err(SerializeError::OutputTooLarge { len: 64, max: 40 })
+        );
+        assert_eq!(SerializeOptions::new().width(0).max_output_len(1).serialize(&data), Err(SerializeError::InvalidWidth(0)));
+
+        let blocked = serialize_blocked(&data, 8).unwrap();
+        assert_eq!(
+            SerializeOptions::new().blocked(8).max_output_len(blocked.len() - 1).serialize(&data),
+            Err(SerializeError::OutputTooLarge { len: blocked.len(), max: blocked.len() - 1 })
+        );
+        assert_eq!(SerializeOptions::new().blocked(8).max_output_len(blocked.len()).serialize(&data), Ok(blocked));
+        let outliers = serialize_with_mode(&data, Mode::Outliers);
+        assert_eq!(
+            SerializeOptions::new().mode(Mode::Outliers).max_output_len(outliers.len() - 1).serialize(&data),
+            Err(SerializeError::OutputTooLarge { len: outliers.len(), max: outliers.len() - 1 })
+        );
+    }
+
+    #[test]
+    fn stream_decoder_byte_by_byte() {
+        let mut spiky: Vec<u16> = (0..300).map(|i| i % 3 + 1).collect();
+        spiky[100] = 300;
+        let inputs = [(1..=300).collect(), phase_change(), spiky, vec![5, 5, 6]];
+        for data in cases().into_iter().map(|(_, data)| data).chain(inputs) {
+            let serialized = serialize_best(&data);
+            let mut decoder = StreamDecoder::new();
+            let mut decoded = Vec::new();
+            for &byte in serialized.as_bytes() {
+                assert!(!decoder.is_finished());
+                decoded.extend(decoder.feed(&[byte]).unwrap());
+            }
+            assert!(decoder.is_finished());
+            assert_eq!(decoder.feed(b"trailing").unwrap(), Vec::<u16>::new());
+            assert!(decoder.buffer.is_empty());
+            assert_eq!(decoded, data);
+        }
+
+        // Состояние сохраняется между кусками: после заголовка читаются только новые символы.
+        let data: Vec<u16> = (0..1000).map(|i| (i * 7 % 300 + 1) as u16).collect();
+        let serialized = serialize(&data);
+        let mut decoder = StreamDecoder::new();
+        let mut decoded = Vec::new();
+        for chunk in serialized.as_bytes().chunks(5) {
+            let before = decoder.pos;
+            decoded.extend(decoder.feed(chunk).unwrap());
+            assert!(decoder.pos >= before);
+        }
+        assert_eq!(decoded, data);
+        assert_eq!(decoder.feed(&vec![b'A'; 1 << 20]), Ok(vec![]));
+        assert!(decoder.buffer.is_empty());
+
+        // Ширина 4 при любых ступенях: заголовок 9 бит, числа — биты 9..21, то есть 4 символа.
+        let serialized = serialize_with_width(&[1, 2, 3], 4).unwrap();
+        let mut decoder = StreamDecoder::new();
+        assert_eq!(decoder.feed(&serialized.as_bytes()[..1]), Ok(vec![]));
+        assert_eq!(decoder.feed(&serialized.as_bytes()[1..3]), Ok(vec![1, 2]));
+        assert_eq!(decoder.feed(b"?"), Err(DeserializeError::InvalidChar { ch: '?', at_char: 3 }));
+    }
+
+    #[test]
+    fn keyed_permutation_round_trip() {
+        let data: Vec<u16> = (1..=300).rev().collect();
+        let keyed = serialize_with_key(&data, 0x5eed);
+        assert_eq!(deserialize_with_key(&keyed, 0x5eed), Ok(data.clone()));
+        assert_eq!(keyed.len(), (serialize_bytes(&data).len() * 8).div_ceil(6));
+        assert_ne!(keyed, serialize_with_key(&data, 0x5eee));
+        assert_ne!(deserialize_with_key(&keyed, 0x5eee), Ok(data));
+        assert!(deserialize_with_key("", 1).is_err());
+
+        let mut permutation = key_permutation(100, 42);
+        permutation.sort();
+        assert_eq!(permutation, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn separators_round_trip() {
+        let numbers = vec![1, 22, 300, 7];
+        for sep in [",", " ", ";", "\n", " | "] {
+            let text = format_numbers(&numbers, sep);
+            let encoded = serialize_best(&parse_numbers_with_sep(&text, sep).unwrap());
+            let decoded = format_numbers(&deserialize(&encoded).unwrap(), sep);
+            assert_eq!(decoded, text, "{:?}", sep);
+        }
+        assert_eq!(format_numbers(&numbers, "\n"), "1\n22\n300\n7");
+        assert_eq!(parse_numbers_with_sep("1;  2;;3;\n", ";"), Ok(vec![1, 2, 3]));
+        assert!(parse_numbers_with_sep("1,2", ";").is_err());
+        assert!(parse_numbers_with_sep("1", "").is_err());
+    }
+
+    #[test]
+    fn parse_numbers_accepts_mixed_separators() {
+        assert_eq!(parse_numbers("1, 2;3\n300"), Ok(vec![1, 2, 3, 300]));
+        assert!(parse_numbers("1,301").is_err());
+        assert!(parse_numbers(" \n").is_err());
+    }
+}
@@ -1,111 +1,190 @@
 use std::fs::OpenOptions;
 use std::io::Write;
 use rand::Rng;
+use serialize2::{
+    analyze_dir, compression_ratio, compression_stats, decode_all_concatenated, deserialize, deserialize_base45,
+    format_numbers, parse_numbers, parse_numbers_with_sep, read_numbers, serialize, serialize_base45, serialize_best,
+    serialize_framed, serialize_stream, CompactBytes, DeserializeError, SerializeError,
+};
 
-const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+fn run_stats(args: &[String]) -> Result<(), String> {
+    let mut input = None;
+    let mut json = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--in" => input = Some(args.next().ok_or("После --in нужен путь к файлу")?),
+            "--json" => json = true,
+            other => return Err(format!("Неизвестный аргумент {:?}", other)),
+        }
+    }
+    let input = input.ok_or("Не указан входной файл (--in)")?;
 
-fn determine_bits_per_number(numbers: &[u16]) -> u8 {
-    let max = *numbers.iter().max().unwrap();
-    if max < 10 {
-        4
-    } else if max < 100 {
-        7
+    let stats = compression_stats(&read_numbers(input)?).map_err(|e| e.to_string())?;
+    if json {
+        println!(
+            "{{\"count\":{},\"trivial_len\":{},\"estimated_len\":{},\"ratio\":{:.3},\"mode\":\"{}\",\"bits_per_value\":{},\"entropy_bits\":{:.1},\"payload_bits\":{},\"efficiency\":{:.3}}}",
+            stats.count,
+            stats.trivial_len,
+            stats.serialized_len,
+            stats.ratio(),
+            stats.mode.name(),
+            stats.bits_per_value,
+            stats.entropy_bits,
+            stats.payload_bits(),
+            stats.efficiency()
+        );
     } else {
-        9
+        println!("Количество чисел: {}", stats.count);
+        println!("Исходный размер (trivial): {}", stats.trivial_len);
+        println!("Оценка размера сериализации: {}", stats.serialized_len);
+        println!("Коэффициент сжатия: {:.3}", stats.ratio());
+        println!("Режим: {}", stats.mode.name());
+        println!("Бит на число: {}", stats.bits_per_value);
+        println!("Энтропия: {:.1} бит, биты чисел: {}", stats.entropy_bits, stats.payload_bits());
+        println!("Эффективность (entropy_bits / payload_bits): {:.3}", stats.efficiency());
     }
+    Ok(())
 }
 
-fn serialize(numbers: &[u16]) -> String {
-    if numbers.iter().any(|&n| n < 1 || n > 300) {
-        panic!("Все числа должны быть в диапазоне 1-300");
+/// Разделитель из `--sep`: `space`, `tab`, `newline`, `comma`, `semicolon` или сам разделитель.
+fn separator(arg: &str) -> String {
+    match arg {
+        "space" => " ",
+        "tab" => "\t",
+        "newline" => "\n",
+        "comma" => ",",
+        "semicolon" => ";",
+        other => other,
     }
+    .to_string()
+}
 
-    let bits_per_number = determine_bits_per_number(numbers);
-    let bits_code = match bits_per_number {
-        4 => "00",
-        7 => "01",
-        9 => "10",
-        _ => panic!("Unsupported bits per number"),
-    };
-
-    let mut bitstr = String::new();
-
-    if numbers.len() < 64 {
-        bitstr.push('0');
-        bitstr += &format!("{:06b}", numbers.len());
-    } else {
-        bitstr.push('1');
-        bitstr += &format!("{:010b}", numbers.len());
+/// Аргументы `encode` и `decode`: вход (`--in`, иначе stdin) и разделитель (`--sep`).
+fn read_codec_args(args: &[String]) -> Result<(String, Option<String>), String> {
+    let mut input = None;
+    let mut sep = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--in" => input = Some(args.next().ok_or("После --in нужен путь к файлу")?),
+            "--sep" => sep = Some(separator(args.next().ok_or("После --sep нужен разделитель")?)),
+            other => return Err(format!("Неизвестный аргумент {:?}", other)),
+        }
     }
 
-    bitstr += bits_code;
+    let text = match input {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| format!("Не удалось прочитать {}: {}", path, e))?,
+        None => std::io::read_to_string(std::io::stdin()).map_err(|e| format!("Не удалось прочитать stdin: {}", e))?,
+    };
+    Ok((text, sep))
+}
 
-    for &num in numbers {
-        bitstr += &format!("{:0width$b}", num, width = bits_per_number as usize);
-    }
+/// Список длиннее 1023 чисел не помещается в одну запись, поэтому пишется кадрами по 1023
+/// (`serialize_framed`) подряд; короткий список — это одна запись `serialize_best`.
+fn encode_numbers(numbers: &[u16]) -> Result<String, SerializeError> {
+    Ok(serialize_framed(numbers, 1023)?.concat())
+}
 
-    let padding = (6 - (bitstr.len() % 6)) % 6;
-    bitstr += &"0".repeat(padding);
+/// Обратное к `encode_numbers`: склеенные записи читаются по очереди.
+fn decode_records(text: &str) -> Result<Vec<u16>, DeserializeError> {
+    Ok(decode_all_concatenated(text.trim())?.concat())
+}
 
-    let mut result = String::new();
-    for chunk in bitstr.as_bytes().chunks(6) {
-        let chunk_str = std::str::from_utf8(chunk).unwrap();
-        let val = u8::from_str_radix(chunk_str, 2).unwrap();
-        result.push(BASE64_CHARS[val as usize] as char);
-    }
+/// Кодирует список чисел; без `--sep` принимаются любые разделители, как в `stats`.
+fn run_encode(args: &[String]) -> Result<(), String> {
+    let (text, sep) = read_codec_args(args)?;
+    let numbers = match sep {
+        Some(sep) => parse_numbers_with_sep(&text, &sep)?,
+        None => parse_numbers(&text)?,
+    };
+    println!("{}", encode_numbers(&numbers).map_err(|e| e.to_string())?);
+    Ok(())
+}
 
-    result
+/// Декодирует запись (или кадры `encode`) и выводит числа через `--sep` (по умолчанию запятая).
+fn run_decode(args: &[String]) -> Result<(), String> {
+    let (text, sep) = read_codec_args(args)?;
+    let numbers = decode_records(&text).map_err(|e| e.to_string())?;
+    println!("{}", format_numbers(&numbers, sep.as_deref().unwrap_or(",")));
+    Ok(())
 }
 
-fn deserialize(s: &str) -> Vec<u16> {
-    let mut bitstr = String::new();
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
     for ch in s.chars() {
-        let index = BASE64_CHARS
-            .iter()
-            .position(|&c| c == ch as u8)
-            .expect("Invalid base64 char");
-        bitstr += &format!("{:06b}", index);
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
     }
+    out.push('"');
+    out
+}
 
-    let mut pos = 0;
-    let flag = &bitstr[pos..pos + 1];
-    pos += 1;
+fn run_analyze(args: &[String]) -> Result<(), String> {
+    let mut dir = None;
+    let mut json = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => dir = Some(args.next().ok_or("После --dir нужен путь к каталогу")?),
+            "--json" => json = true,
+            other => return Err(format!("Неизвестный аргумент {:?}", other)),
+        }
+    }
+    let dir = dir.ok_or("Не указан каталог (--dir)")?;
 
-    let count: usize = if flag == "0" {
-        let len = usize::from_str_radix(&bitstr[pos..pos + 6], 2).unwrap();
-        pos += 6;
-        len
+    let report = analyze_dir(dir)?;
+    if json {
+        let files: Vec<String> = report
+            .files
+            .iter()
+            .map(|file| {
+                format!(
+                    "{{\"path\":{},\"trivial_len\":{},\"serialized_len\":{},\"ratio\":{:.3}}}",
+                    json_string(&file.path.to_string_lossy()),
+                    file.stats.trivial_len,
+                    file.stats.serialized_len,
+                    file.stats.ratio()
+                )
+            })
+            .collect();
+        let skipped: Vec<String> = report
+            .skipped
+            .iter()
+            .map(|(path, reason)| {
+                format!("{{\"path\":{},\"reason\":{}}}", json_string(&path.to_string_lossy()), json_string(reason))
+            })
+            .collect();
+        println!(
+            "{{\"files\":[{}],\"skipped\":[{}],\"trivial_len\":{},\"serialized_len\":{},\"ratio\":{:.3},\"savings\":{:.3}}}",
+            files.join(","),
+            skipped.join(","),
+            report.trivial_len(),
+            report.serialized_len(),
+            report.ratio(),
+            report.savings()
+        );
     } else {
-        let len = usize::from_str_radix(&bitstr[pos..pos + 10], 2).unwrap();
-        pos += 10;
-        len
-    };
-
-    let bits_code = &bitstr[pos..pos + 2];
-    pos += 2;
-
-    let bits_per_number = match bits_code {
-        "00" => 4,
-        "01" => 7,
-        "10" => 9,
-        _ => panic!("Invalid bits code"),
-    };
-
-    let mut numbers = Vec::new();
-    for _ in 0..count {
-        let chunk = &bitstr[pos..pos + bits_per_number];
-        let num = u16::from_str_radix(chunk, 2).unwrap();
-        numbers.push(num);
-        pos += bits_per_number;
+        for file in &report.files {
+            println!(
+                "{}: {} -> {} символов, коэффициент {:.3}",
+                file.path.display(),
+                file.stats.trivial_len,
+                file.stats.serialized_len,
+                file.stats.ratio()
+            );
+        }
+        for (path, reason) in &report.skipped {
+            println!("{}: пропущен ({})", path.display(), reason);
+        }
+        println!("Итого: {} -> {} символов", report.trivial_len(), report.serialized_len());
+        println!("Коэффициент сжатия: {:.3}, экономия: {:.1}%", report.ratio(), report.savings() * 100.0);
     }
-
-    numbers
-}
-
-fn compression_ratio(numbers: &[u16]) -> f64 {
-    let trivial = numbers.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(",");
-    let serialized = serialize(numbers);
-    serialized.len() as f64 / trivial.len() as f64
+    Ok(())
 }
 
 fn log(message: &str) {
@@ -162,7 +241,13 @@ fn run_tests() {
         let trivial = data.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(",");
         let serialized = serialize(&data);
         let deserialized = deserialize(&serialized);
+        let compact = CompactBytes::from(data.as_slice());
+        let compact_len = compact.0.len();
+        let from_compact = Vec::<u16>::try_from(compact);
+        let base45 = serialize_base45(&data);
+        let best = serialize_best(&data);
         let ratio = compression_ratio(&data);
+        let stats = compression_stats(&data).unwrap();
 
         log(&format!("Тест: {}", desc));
         log(&format!("Исходная строка (trivial): {}{}", 
@@ -171,11 +256,87 @@ fn run_tests() {
         ));
         log(&format!("Сериализованная строка: {}", serialized));
         log(&format!("Коэффициент сжатия: {:.3}", ratio));
-        log(&format!("Десериализованный массив корректен? {}", if deserialized == data { "Да" } else { "Нет" }));
+        log(&format!(
+            "Энтропия: {:.1} бит, биты чисел: {}, efficiency = entropy_bits / payload_bits = {:.3}",
+            stats.entropy_bits, stats.payload_bits(), stats.efficiency()
+        ));
+        log(&format!("Десериализованный массив корректен? {}", if deserialized.as_ref() == Ok(&data) { "Да" } else { "Нет" }));
+        log(&format!("Бинарная форма: {} байт, корректна? {}", compact_len, if from_compact.as_ref() == Ok(&data) { "Да" } else { "Нет" }));
+        log(&format!("Лучший режим (serialize_best): {} символов, корректен? {}", best.len(), if deserialize(&best).as_ref() == Ok(&data) { "Да" } else { "Нет" }));
+        log(&format!("Base45 для QR: {} символов, корректна? {}", base45.len(), if deserialize_base45(&base45).as_ref() == Ok(&data) { "Да" } else { "Нет" }));
+        log("------------------------------------------------------");
+    }
+}
+
+/// Те же типы данных, что в `run_tests`, но по миллиону чисел и больше: числа генерируются
+/// на лету и кодируются `serialize_stream`, вход целиком в памяти не держится.
+fn run_stress() -> Result<(), String> {
+    let cases: Vec<(&str, Box<dyn Iterator<Item = u16>>)> = vec![
+        (
+            "Случайные 3 000 000 чисел",
+            Box::new({
+                let mut rng = rand::rng();
+                (0..3_000_000).map(move |_| rng.random_range(1..=300))
+            }),
+        ),
+        (
+            "Все 3-значные (100..300), 1 000 000 чисел",
+            Box::new((0..1_000_000).map(|i| (100 + (i % 201)) as u16)),
+        ),
+        (
+            "Каждого числа по 3 раза (от 1 до 300), 1000 повторов, итого 900 000 чисел",
+            Box::new((0..1000).flat_map(|_| (1..=300).flat_map(|n| std::iter::repeat_n(n, 3)))),
+        ),
+    ];
+
+    for (desc, numbers) in cases {
+        let stats = serialize_stream(numbers, std::io::sink()).map_err(|e| e.to_string())?;
+        log(&format!("Стресс-тест: {}", desc));
+        log(&format!("Чисел: {}, кадров: {}", stats.count, stats.frames));
+        log(&format!("Исходный размер (trivial): {}, сериализованный: {}", stats.trivial_len, stats.serialized_len));
+        log(&format!("Коэффициент сжатия: {:.3}", stats.ratio()));
         log("------------------------------------------------------");
     }
+    Ok(())
 }
 
+const USAGE: &str = "Использование: serialize2 [--stress | stats --in <файл> [--json] | analyze --dir <каталог> [--json] \
+                     | encode [--in <файл>] [--sep <разделитель>] | decode [--in <файл>] [--sep <разделитель>]]";
+
 fn main() {
-    run_tests();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        None => {
+            run_tests();
+            Ok(())
+        }
+        Some("--stress") => run_stress(),
+        Some("stats") => run_stats(&args[1..]),
+        Some("analyze") => run_analyze(&args[1..]),
+        Some("encode") => run_encode(&args[1..]),
+        Some("decode") => run_decode(&args[1..]),
+        Some(_) => Err(USAGE.to_string()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_long_lists() {
+        let short: Vec<u16> = (1..=300).collect();
+        assert_eq!(encode_numbers(&short), Ok(serialize_best(&short)));
+        assert_eq!(decode_records(&format!("{}\n", encode_numbers(&short).unwrap())), Ok(short));
+
+        let long: Vec<u16> = (0..1500).map(|i| (i % 300 + 1) as u16).collect();
+        let encoded = encode_numbers(&long).unwrap();
+        assert_eq!(decode_records(&encoded), Ok(long));
+        assert!(decode_records("Ae").is_err());
+    }
 }